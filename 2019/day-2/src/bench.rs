@@ -0,0 +1,231 @@
+
+/*!
+A benchmark of the interpreter on the full noun and verb sweep.
+
+The sweep runs the program once for every noun and verb, through the current
+`ProgramState` and through a reference copy of the step loop it replaced, so
+every timing comes with a baseline from the same machine and build.
+
+The reference loop boxes a fresh opcode from a factory for every step, and
+decodes the instruction again for every parameter it reads, as the
+interpreter did before its opcodes were dispatched through statics. It only
+knows the add, multiply and halt opcodes day 2 uses.
+*/
+
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use intcode::cell::{self, Cell};
+use intcode::error::ErrorKind;
+use intcode::memory::Memory;
+use intcode::{Instruction, ParameterMode, ProgramState};
+
+use crate::search::RunGuard;
+
+/// Runs a sweep of a program against a target, returning the matches.
+type Sweep = fn(&[Cell], &RangeInclusive<i64>, &Cell, RunGuard) -> usize;
+
+/// How long each interpreter took for the same sweeps.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub runs_per_sweep: u64,
+
+    // The matches each interpreter found, which only differ when the program
+    // uses opcodes the reference doesn't know.
+    //
+    pub matches: usize,
+    pub reference_matches: usize,
+
+    pub current: Duration,
+    pub reference: Duration,
+}
+
+impl Comparison {
+
+    /// How many times faster the current interpreter is.
+    pub fn speedup(&self) -> f64 {
+        self.reference.as_secs_f64() / self.current.as_secs_f64()
+    }
+}
+
+/// Time `rounds` sweeps of every noun and verb in `bounds` on each
+/// interpreter, with every run stopped by `guard` if it doesn't halt.
+pub fn compare(instructions: &[Cell], bounds: RangeInclusive<i64>, target: &Cell, rounds: u32, guard: RunGuard) -> Comparison {
+    let width = (bounds.end() - bounds.start() + 1).max(0) as u64;
+
+    // Warm up once each, so the first round doesn't pay for faulting in the
+    // binary.
+    //
+    let matches = sweep(instructions, &bounds, target, guard);
+    let reference_matches = reference_sweep(instructions, &bounds, target, guard);
+
+    let time = |sweep: Sweep| {
+        let start = Instant::now();
+        for _ in 0..rounds {
+            sweep(instructions, &bounds, target, guard);
+        }
+        start.elapsed()
+    };
+
+    Comparison {
+        runs_per_sweep: width * width,
+        matches,
+        reference_matches,
+        current: time(sweep),
+        reference: time(reference_sweep),
+    }
+}
+
+/// Run the program once for every noun and verb in `bounds`, returning how
+/// many runs halted with `target` at position 0.
+pub fn sweep(instructions: &[Cell], bounds: &RangeInclusive<i64>, target: &Cell, guard: RunGuard) -> usize {
+    let mut matches = 0;
+
+    for noun in bounds.clone() {
+        for verb in bounds.clone() {
+            let mut state = ProgramState::from_vec(instructions.to_vec());
+            state.patch_noun_and_verb(cell::from_i64(noun), cell::from_i64(verb));
+            guard.apply(&mut state);
+
+            if state.execute().is_ok() && state.read(0) == *target {
+                matches += 1;
+            }
+        }
+    }
+
+    matches
+}
+
+/// The same sweep on the reference interpreter.
+pub fn reference_sweep(instructions: &[Cell], bounds: &RangeInclusive<i64>, target: &Cell, guard: RunGuard) -> usize {
+    let mut matches = 0;
+
+    for noun in bounds.clone() {
+        for verb in bounds.clone() {
+            let mut machine = ReferenceMachine::new(instructions.to_vec(), guard.step_limit);
+            machine.memory.write(1, cell::from_i64(noun));
+            machine.memory.write(2, cell::from_i64(verb));
+
+            if machine.execute().is_ok() && machine.memory.read(0) == *target {
+                matches += 1;
+            }
+        }
+    }
+
+    matches
+}
+
+trait ReferenceOpCode {
+    fn parameter_count(&self) -> usize;
+
+    /// Execute the opcode, returning true if the program halted.
+    fn execute(&self, machine: &mut ReferenceMachine) -> Result<bool, ErrorKind>;
+}
+
+struct ReferenceArithmetic(fn(&Cell, &Cell) -> Option<Cell>);
+impl ReferenceOpCode for ReferenceArithmetic {
+    fn parameter_count(&self) -> usize {
+        3
+    }
+
+    fn execute(&self, machine: &mut ReferenceMachine) -> Result<bool, ErrorKind> {
+        let arg_one = machine.get_arg(1)?;
+        let arg_two = machine.get_arg(2)?;
+        let value = (self.0)(&arg_one, &arg_two).ok_or(ErrorKind::Overflow)?;
+
+        let address = machine.parameter_address(3)?;
+        machine.memory.write(address, value);
+        Ok(false)
+    }
+}
+
+struct ReferenceHalt;
+impl ReferenceOpCode for ReferenceHalt {
+    fn parameter_count(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _machine: &mut ReferenceMachine) -> Result<bool, ErrorKind> {
+        Ok(true)
+    }
+}
+
+fn reference_opcode_factory(code: u32) -> Result<Box<dyn ReferenceOpCode>, ErrorKind> {
+    match code {
+        1 => Ok(Box::new(ReferenceArithmetic(cell::checked_add))),
+        2 => Ok(Box::new(ReferenceArithmetic(cell::checked_mul))),
+        99 => Ok(Box::new(ReferenceHalt)),
+        _ => Err(ErrorKind::UnknownOpcode),
+    }
+}
+
+struct ReferenceMachine {
+    memory: Memory,
+    position: usize,
+    steps: u64,
+    step_limit: Option<u64>,
+}
+
+impl ReferenceMachine {
+
+    fn new(instructions: Vec<Cell>, step_limit: Option<u64>) -> Self {
+        Self { memory: Memory::from_vec(instructions), position: 0, steps: 0, step_limit }
+    }
+
+    fn mode(&self, index: usize) -> Result<ParameterMode, ErrorKind> {
+        Ok(Instruction::decode(&self.memory.read(self.position))?.modes[index - 1])
+    }
+
+    fn parameter_address(&self, index: usize) -> Result<usize, ErrorKind> {
+        let parameter = self.memory.read(self.position + index);
+
+        match self.mode(index)? {
+            ParameterMode::Position => cell::to_usize(&parameter).ok_or(ErrorKind::BadAddress(parameter)),
+            ParameterMode::Immediate => Err(ErrorKind::BadParameterMode { parameter: index, mode: 1 }),
+            ParameterMode::Relative => Err(ErrorKind::BadParameterMode { parameter: index, mode: 2 }),
+        }
+    }
+
+    fn get_arg(&self, index: usize) -> Result<Cell, ErrorKind> {
+        match self.mode(index)? {
+            ParameterMode::Immediate => Ok(self.memory.read(self.position + index)),
+            _ => Ok(self.memory.read(self.parameter_address(index)?)),
+        }
+    }
+
+    fn execute(&mut self) -> Result<(), ErrorKind> {
+        loop {
+            if let Some(limit) = self.step_limit {
+                if self.steps >= limit {
+                    return Err(ErrorKind::StepLimitExceeded(limit));
+                }
+            }
+
+            let instruction = Instruction::decode(&self.memory.read(self.position))?;
+            let op_action = reference_opcode_factory(instruction.opcode)?;
+
+            self.steps += 1;
+            if op_action.execute(self)? {
+                return Ok(());
+            }
+
+            self.position += 1 + op_action.parameter_count();
+        }
+    }
+}
+
+#[test]
+fn test_bench_sweeps() {
+    let instructions = intcode::loader::parse(include_str!("part-one-input.txt")).unwrap();
+    let target = Cell::from(19690720);
+
+    assert_eq!(sweep(&instructions, &(0..=99), &target, RunGuard::default()), 1);
+    assert_eq!(reference_sweep(&instructions, &(0..=99), &target, RunGuard::default()), 1);
+
+    // A noun of 1 with a verb of 0 jumps back to the start forever, and
+    // only the guard stops it.
+    //
+    let instructions = cell::from_slice(&[1105,0,0,99]);
+    let guard = RunGuard { step_limit: Some(10), detect_loops: false };
+    assert_eq!(sweep(&instructions, &(0..=1), &Cell::from(1105), guard), 2);
+}
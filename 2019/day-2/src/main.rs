@@ -136,255 +136,279 @@ Solution:
 */
 
 use std::env;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-
-#[derive(PartialEq)]
-enum Outcome {
-
-    // Continue executing.
-    //
-    Continue,
-
-    // Halt execution
-    //
-    Halt, 
+use std::io;
+use std::ops::RangeInclusive;
+use std::process;
+
+use intcode::cell::Cell;
+use intcode::{debugger, loader, symbolic, ProgramState};
+
+mod bench;
+mod search;
+
+const USAGE: &str = "\
+usage: aoc-2019-day-2 <file>
+       aoc-2019-day-2 <command> <file> [arguments...]
+
+  <file>                                   Solve both parts of the puzzle.
+  search <file> <target> [options...]      Search nouns and verbs on every core for a target.
+      --first, --detect-loops, --steps=N, and noun then verb bounds like 0..99
+  symbolic <file> <target> [patches...]    Solve for patched cells, like 1=0..99, symbolically.
+  bench <file> [rounds]                    Time the noun and verb sweep against the reference.
+  debug <file> [noun verb]                 Step through the program in the debugger.
+
+Other Intcode tools and days live in the intcode binary.
+";
+
+/// Load the program named by `filename`, exiting with the reason if it
+/// can't be loaded.
+fn load_or_exit(filename: &str) -> Vec<Cell> {
+    loader::load_arg(filename).unwrap_or_else(|error| {
+        eprintln!("Failed to load {}: {}", filename, error);
+        process::exit(1);
+    })
 }
 
-trait OpCode {
-    fn execute(&self, state: &mut ProgramState) -> Outcome;
+/// Parse an inclusive range like `0..99`.
+fn parse_range(text: &str) -> Option<RangeInclusive<i64>> {
+    let (low, high) = text.split_once("..")?;
+    Some(low.trim().parse().ok()?..=high.trim().parse().ok()?)
 }
 
-/// Implementation of a halt op code.
-#[derive(Default)]
-struct HaltOpCode;
-impl OpCode for HaltOpCode {
-    fn execute(&self, _state: &mut ProgramState) -> Outcome {
-        return Outcome::Halt;
+/// Search every noun and verb in the bounds for the ones that leave `target`
+/// at position 0, on every core. Bounds are inclusive ranges like `0..99`,
+/// and `--first` stops the search at the first match. Each run is limited to
+/// `--steps=N` steps, and `--detect-loops` also stops any run that loops.
+fn search_noun_and_verb(filename: &str, target: &str, options: &[String]) {
+    let instructions = load_or_exit(filename);
+
+    let target = match target.parse::<Cell>() {
+        Ok(target) => target,
+        Err(_) => {
+            println!("Not a valid target: {}", target);
+            return;
+        }
+    };
+
+    let mut stop_at_first = false;
+    let mut guard = search::RunGuard::default();
+    let mut bounds = Vec::new();
+
+    for option in options {
+        if option == "--first" {
+            stop_at_first = true;
+        } else if option == "--detect-loops" {
+            guard.detect_loops = true;
+        } else if let Some(limit) = option.strip_prefix("--steps=") {
+            match limit.parse() {
+                Ok(limit) => guard.step_limit = Some(limit),
+                Err(_) => {
+                    println!("Not a valid step limit: {}", limit);
+                    return;
+                }
+            }
+        } else {
+            bounds.push(option);
+        }
     }
-}
 
-/// Implementation of an addition op code.
-#[derive(Default)]
-struct AddOpCode;
-impl OpCode for AddOpCode {
-    fn execute(&self, state: &mut ProgramState) -> Outcome {
+    let ranges: Option<Vec<RangeInclusive<i64>>> = bounds.iter().map(|option| parse_range(option)).collect();
 
-        let arg_one = state.get_arg_one();
-        let arg_two = state.get_arg_two();
+    let (nouns, verbs) = match ranges.as_deref() {
+        Some([]) => (0..=search::NOUN_VERB_LIMIT, 0..=search::NOUN_VERB_LIMIT),
+        Some([nouns]) => (nouns.clone(), 0..=search::NOUN_VERB_LIMIT),
+        Some([nouns, verbs]) => (nouns.clone(), verbs.clone()),
+        _ => {
+            println!("Bounds should look like 0..99, for the nouns and then the verbs");
+            return;
+        }
+    };
 
-        state.store_result(arg_one + arg_two);
+    let workers = search::default_workers();
+    let result = search::search(&instructions, nouns, verbs, &target, stop_at_first, workers, guard);
 
-        return Outcome::Continue;
+    for (noun, verb) in &result.matches {
+        println!("100 * noun({}) + verb({}) = {}", noun, verb, 100 * noun + verb);
     }
+
+    println!("{} matches for {} from {} runs on {} workers ({} runs failed, {} looped, {} ran out of steps)",
+        result.matches.len(), target, result.runs, workers, result.failures, result.loops, result.step_limits);
 }
 
-/// Implementation of a multiplication op code.
-#[derive(Default)]
-struct MultOpCode;
-impl OpCode for MultOpCode {
-    fn execute(&self, state: &mut ProgramState) -> Outcome {
+/// Solve for patched cells that leave `target` at position 0, without
+/// running the program for each candidate. Each patch is given as
+/// `address=low..high`, inclusive, and defaults to the noun and verb.
+fn solve_symbolic(filename: &str, target: &str, patches: &[String]) {
+    let instructions = load_or_exit(filename);
+
+    let target = match target.parse::<Cell>() {
+        Ok(target) => target,
+        Err(_) => {
+            println!("Not a valid target: {}", target);
+            return;
+        }
+    };
 
-        let arg_one = state.get_arg_one();
-        let arg_two = state.get_arg_two();
+    let parse_patch = |patch: &str| -> Option<(usize, RangeInclusive<i64>)> {
+        let (address, range) = patch.split_once('=')?;
+        Some((address.parse().ok()?, parse_range(range)?))
+    };
 
-        state.store_result(arg_one * arg_two);
+    let patches: Vec<(usize, RangeInclusive<i64>)> = if patches.is_empty() {
+        vec![(1, 0..=99), (2, 0..=99)]
+    } else {
+        match patches.iter().map(|patch| parse_patch(patch)).collect() {
+            Some(patches) => patches,
+            None => {
+                println!("Patches should look like 1=0..99");
+                return;
+            }
+        }
+    };
 
-        return Outcome::Continue;
-    }
-}
+    let addresses: Vec<usize> = patches.iter().map(|(address, _)| *address).collect();
+    let bounds: Vec<RangeInclusive<i64>> = patches.into_iter().map(|(_, range)| range).collect();
 
-struct ProgramState<'a> {
-    current_position: usize,
-    state: &'a mut Vec<u32>,
-}
+    let state = ProgramState::from_vec(instructions);
+    let output = match state.execute_symbolic(&addresses).and_then(|memory| memory.value(0)) {
+        Ok(output) => output,
+        Err(error) => {
+            println!("Symbolic execution failed: {}", error);
+            return;
+        }
+    };
 
-impl<'a> ProgramState<'a> {
+    println!("[0] = {}", output.display(&addresses));
 
-    const INSTRUCTION_INCREMENT: usize = 4;
+    let solutions = symbolic::solve(&output, &bounds, &target);
+    for solution in &solutions {
+        let values: Vec<String> = addresses.iter()
+            .zip(solution)
+            .map(|(address, value)| format!("[{}]={}", address, value))
+            .collect();
 
-    pub fn from_vec(instruction_stream: &'a mut Vec<u32>) -> Self {
-        Self { 
-            current_position: 0,
-            state: instruction_stream
-        }
+        println!("{}", values.join(" "));
     }
 
-    pub fn get_arg_one(&self) -> u32 {
-        let position = self.state[self.current_position + 1] as usize;
-        self.state[position]
-    }
+    println!("{} solutions for {}", solutions.len(), target);
+}
 
-    pub fn get_arg_two(&self) -> u32 {
-        let position = self.state[self.current_position + 2] as usize;
-        self.state[position]
-    }
+/// Time the full noun and verb sweep on the current interpreter and on the
+/// reference one it replaced, to measure the interpreter's speed.
+fn bench(filename: &str, rounds: Option<&String>) {
+    let instructions = load_or_exit(filename);
+
+    let rounds = match rounds.map(|rounds| rounds.parse::<u32>()) {
+        None => 10,
+        Some(Ok(rounds)) if rounds > 0 => rounds,
+        Some(_) => {
+            println!("Not a valid number of rounds: {}", rounds.map(String::as_str).unwrap_or_default());
+            return;
+        }
+    };
 
-    pub fn store_result(&mut self, value: u32) {
-        let position = self.state[self.current_position + 3] as usize;
-        self.state[position] = value;
-    }
+    let target = Cell::from(19690720);
+    let comparison = bench::compare(&instructions, 0..=search::NOUN_VERB_LIMIT, &target, rounds, search::RunGuard::default());
+    let runs = comparison.runs_per_sweep * u64::from(rounds);
 
-    /// Restore the gravity assist program to the "1202 program alarm" state it
-    /// had just before the last computer caught fire. To do this, before running
-    /// the program, replace position 1 with the value 12 and replace position 2 with the value 2. 
-    pub fn set_1202_alarm(&mut self) {
-        self.state[1] = 12;
-        self.state[2] = 02;
+    println!("{} rounds of {} runs ({} matching)", rounds, comparison.runs_per_sweep, comparison.matches);
+    if comparison.reference_matches != comparison.matches {
+        println!("The reference found {} matches, so the program uses opcodes it doesn't know", comparison.reference_matches);
     }
 
-    /// In this program, the value placed in address 1 is called the noun,
-    /// and the value placed in address 2 is called the verb. 
-    pub fn patch_noun_and_verb(&mut self, noun: u32, verb: u32) {
-        self.state[1] = noun;
-        self.state[2] = verb 
+    for (name, elapsed) in &[("current", comparison.current), ("reference", comparison.reference)] {
+        println!("{:>9}: {:.3?} per sweep, {:.0} runs per second",
+            name, *elapsed / rounds, runs as f64 / elapsed.as_secs_f64());
     }
 
-    pub fn execute(&mut self) {
-
-        let mut state = Outcome::Continue;
-
-        while state == Outcome::Continue {
-            let op_code = self.state[self.current_position];
-            let op_action = self.opcode_factory(op_code);
-
-            state = op_action.execute(self);
+    println!("The current interpreter is {:.2}x the speed of the reference", comparison.speedup());
+}
 
-            // Jump forward the instruction increment.
-            //
-            self.current_position += ProgramState::INSTRUCTION_INCREMENT;
+/// Load the program, patch in the noun and verb if given, and drop into the
+/// step debugger before its first instruction.
+fn debug(filename: &str, patches: &[String]) {
+    let mut state = ProgramState::from_vec(load_or_exit(filename));
+
+    if let [noun, verb] = patches {
+        match (noun.parse::<Cell>(), verb.parse::<Cell>()) {
+            (Ok(noun), Ok(verb)) => state.patch_noun_and_verb(noun, verb),
+            _ => {
+                println!("Not a valid noun and verb: {} {}", noun, verb);
+                return;
+            }
         }
     }
 
-    fn opcode_factory(&self, code: u32) -> Box<dyn OpCode> {
-        match code {
-            01 => Box::new(AddOpCode::default()),
-            02 => Box::new(MultOpCode::default()),
-            99 => Box::new(HaltOpCode::default()),
-            _ => panic!("Unknown opcode: {}", code),
-        }
+    let mut debugger = debugger::Debugger::new(state);
+    if let Err(error) = debugger.run(debugger::stdin_commands(), &mut io::stdout()) {
+        println!("Debugger failed: {}", error);
     }
 }
 
+fn solve(filename: &str) {
 
-#[cfg(test)]
-fn assert_input_output(input: &mut Vec<u32>, expected: &mut Vec<u32>) {
-
-    let mut program_state = ProgramState::from_vec(input);
-    program_state.execute();
-
-    // The input would have been modified.
-    //
-    assert_eq!(expected, input);
-}
+    println!("Parsing file: {}", filename);
 
-#[test]
-fn test_program_execution_add() {
-    // Execute a program that generates it's own terminates opcode.
-    //
-    let mut instructions = vec![1,6,5,4,0,98,1];
-    let mut program_state = ProgramState::from_vec(&mut instructions);
+    let instructions = load_or_exit(filename);
 
-    program_state.execute();
-}
-
-#[test]
-fn test_program_execution_mult() {
-    // Execute a program that generates it's own terminates opcode.
-    //
-    let mut instructions = vec![2,6,5,4,0,99,1];
-    let mut program_state = ProgramState::from_vec(&mut instructions);
-
-    program_state.execute();
-}
-
-#[test]
-fn test_program_execution_samples() {
+    // Part One
     {
-        let mut input = vec![1,0,0,0,99];
-        let mut expected = vec![2,0,0,0,99];
-        assert_input_output(&mut input, &mut expected);
-    }
+        let mut state = ProgramState::from_vec(instructions.clone());
+        state.set_1202_alarm();
 
-    {
-        let mut input = vec![2,3,0,3,99];
-        let mut expected = vec![2,3,0,6,99];
-        assert_input_output(&mut input, &mut expected);
+        match state.execute() {
+            Ok(_) => println!("End state: {}", state.read(0)),
+            Err(error) => println!("Part one failed: {}", error),
+        }
     }
     
-    {
-        let mut input = vec![2,4,4,5,99,0];
-        let mut expected = vec![2,4,4,5,99,9801];
-        assert_input_output(&mut input, &mut expected);
-    }
-
-    {
-        let mut input = vec![1,1,1,4,99,5,6,0,99];
-        let mut expected = vec![30,1,1,4,2,5,6,0,99];
-        assert_input_output(&mut input, &mut expected);
+    // Part Two, searched on every core, stopping at the first match.
+    let bounds = 0..=search::NOUN_VERB_LIMIT;
+    let result = search::search(&instructions, bounds.clone(), bounds, &Cell::from(19690720), true, search::default_workers(), search::RunGuard::default());
+
+    match result.matches.first() {
+        // What is 100 * noun + verb? )
+        Some((noun, verb)) => println!("100 * noun({}) + verb({}) = {}", noun, verb, 100 * noun + verb),
+        None => println!("No noun and verb produce 19690720 ({} runs failed, {} looped, {} ran out of steps)",
+            result.failures, result.loops, result.step_limits),
     }
 }
 
-fn process_file(filename: &str) -> Vec<u32> {
-
-    let file = File::open(filename).unwrap();
-    let reader = BufReader::new(file);
-
-    let mut instructions = Vec::new();
+fn main() {
+    let args: Vec<String> = env::args().collect();
 
-    for line in reader.lines() {
-        for value in line.unwrap().split(",") {
-            instructions.push(value.parse::<u32>().unwrap());
+    match args.as_slice() {
+        [_, filename] => solve(filename),
+        [_, command, filename, target, options @ ..] if command == "search" => search_noun_and_verb(filename, target, options),
+        [_, command, filename, target, patches @ ..] if command == "symbolic" => solve_symbolic(filename, target, patches),
+        [_, command, filename, rounds @ ..] if command == "bench" && rounds.len() <= 1 => bench(filename, rounds.first()),
+        [_, command, filename, patches @ ..] if command == "debug" && (patches.is_empty() || patches.len() == 2) => {
+            debug(filename, patches)
+        }
+        _ => {
+            eprint!("{}", USAGE);
+            process::exit(2);
         }
     }
-
-    return instructions;
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() == 2 {
-
-        let filename = &args[1];
-
-        println!("Parsing file: {}", filename);
-        /*
-        Part One:
-
-        let mut instruction = instructions.clone()
-        let mut state = ProgramState::from_vec(&mut instruction);
-        state.set_1202_alarm();
-        state.execute();
-
-        println!("End state: {}", instructions[0]);
-        */
-        
-        // Part Two
-        let instructions = process_file(filename);
-
-        for noun in 0..152 {
-            for verb in 0..152 {
-
-                let mut instruction_clone = instructions.clone();
-                let mut state = ProgramState::from_vec(&mut instruction_clone);
+#[test]
+fn test_symbolic_day_2_input() {
+    use intcode::cell::from_slice;
+    use intcode::memory::Memory;
 
-                // Patch state as instructed above.
-                state.patch_noun_and_verb(noun, verb);
+    let image = loader::parse(include_str!("part-one-input.txt")).unwrap();
 
-                // Execute program
-                state.execute();
+    let memory = symbolic::execute(&Memory::from_vec(image), 0, &Cell::from(0), &[1, 2]).unwrap();
+    let output = memory.value(0).unwrap();
 
-                if instruction_clone[0] == 19690720 {
-                    // What is 100 * noun + verb? )
-                    println!("100 * noun({}) + verb({}) = {}", noun, verb, 100 * noun + verb);
+    assert_eq!(output.degree_in(0), 1);
+    assert_eq!(output.degree_in(1), 1);
 
-                    return;
-                }
-            }
-        }
+    // The polynomial agrees with running the program normally.
+    //
+    assert_eq!(output.evaluate(&from_slice(&[12, 2])), Some(Cell::from(3562672)));
 
-    } else {
-        println!("Missing input file");
-    }
+    let solutions = symbolic::solve(&output, &[0..=99, 0..=99], &Cell::from(19690720));
+    assert_eq!(solutions, vec![from_slice(&[82, 50])]);
 }
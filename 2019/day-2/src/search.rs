@@ -0,0 +1,196 @@
+
+/*!
+Parallel brute force search for the noun and verb that produce a target.
+
+The nouns are shared out between one worker thread per core. Each worker
+takes the next unsearched noun, runs the program for every verb with it, and
+comes back for another, so a slow row doesn't hold the others up. When only
+the first match is wanted, a shared flag stops every worker as soon as any
+of them finds one.
+
+A bad patch can leave the program running forever, so every run is guarded
+by a step limit. Loop detection can also stop a run the moment it repeats a
+state, though hashing memory every step makes the whole search many times
+slower. Runs stopped either way are counted apart from the ones that fail.
+*/
+
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use intcode::cell::{self, Cell};
+use intcode::error::ErrorKind;
+use intcode::ProgramState;
+
+/// The default number of steps a single run may take. The puzzle's
+/// programs halt after a few dozen.
+pub const DEFAULT_STEP_LIMIT: u64 = 100_000;
+
+/// The largest noun and verb the brute force searches try by default.
+pub const NOUN_VERB_LIMIT: i64 = 151;
+
+/// How each run is stopped if its patch keeps it from halting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RunGuard {
+    pub step_limit: Option<u64>,
+    pub detect_loops: bool,
+}
+
+impl Default for RunGuard {
+    fn default() -> Self {
+        Self { step_limit: Some(DEFAULT_STEP_LIMIT), detect_loops: false }
+    }
+}
+
+impl RunGuard {
+
+    /// Guard the runs of `state`.
+    pub fn apply(&self, state: &mut ProgramState) {
+        state.set_loop_detection(self.detect_loops);
+        if let Some(limit) = self.step_limit {
+            state.set_step_limit(limit);
+        }
+    }
+}
+
+/// What a search found.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchResult {
+
+    // Every noun and verb that produced the target, in ascending order.
+    //
+    pub matches: Vec<(i64, i64)>,
+
+    // The number of runs made, and how many of them failed to execute.
+    //
+    pub runs: u64,
+    pub failures: u64,
+
+    // Runs stopped by the guard, for going into a loop or running out of steps.
+    //
+    pub loops: u64,
+    pub step_limits: u64,
+}
+
+/// Search every noun and verb in the bounds for the ones that leave `target`
+/// at position 0, using `workers` threads, with each run stopped by `guard`
+/// if it doesn't halt. With `stop_at_first` the search is cancelled once any
+/// match is found.
+pub fn search(instructions: &[Cell], nouns: RangeInclusive<i64>, verbs: RangeInclusive<i64>, target: &Cell, stop_at_first: bool, workers: usize, guard: RunGuard) -> SearchResult {
+    let next_noun = AtomicI64::new(*nouns.start());
+    let stop = AtomicBool::new(false);
+    let runs = AtomicU64::new(0);
+    let failures = AtomicU64::new(0);
+    let loops = AtomicU64::new(0);
+    let step_limits = AtomicU64::new(0);
+    let matches = Mutex::new(Vec::new());
+
+    let worker = || {
+        while !stop.load(Ordering::Relaxed) {
+            let noun = next_noun.fetch_add(1, Ordering::Relaxed);
+            if !nouns.contains(&noun) {
+                return;
+            }
+
+            for verb in verbs.clone() {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+
+                let mut state = ProgramState::from_vec(instructions.to_vec());
+                state.patch_noun_and_verb(cell::from_i64(noun), cell::from_i64(verb));
+                guard.apply(&mut state);
+
+                runs.fetch_add(1, Ordering::Relaxed);
+
+                if let Err(error) = state.execute() {
+                    let counter = match error.kind {
+                        ErrorKind::InfiniteLoop { .. } => &loops,
+                        ErrorKind::StepLimitExceeded(_) => &step_limits,
+                        _ => &failures,
+                    };
+
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+
+                if state.read(0) == *target {
+                    matches.lock().unwrap().push((noun, verb));
+
+                    if stop_at_first {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    };
+
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(worker);
+        }
+    });
+
+    let mut matches = matches.into_inner().unwrap();
+    matches.sort_unstable();
+
+    SearchResult {
+        matches,
+        runs: runs.into_inner(),
+        failures: failures.into_inner(),
+        loops: loops.into_inner(),
+        step_limits: step_limits.into_inner(),
+    }
+}
+
+/// One worker per available core.
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map(|count| count.get()).unwrap_or(1)
+}
+
+#[cfg(test)]
+fn day_2_input() -> Vec<Cell> {
+    intcode::loader::parse(include_str!("part-one-input.txt")).unwrap()
+}
+
+#[test]
+fn test_search_all_matches() {
+    // Add the noun and verb as immediates.
+    //
+    let instructions = cell::from_slice(&[1101,0,0,0,99]);
+
+    let result = search(&instructions, 0..=9, -2..=9, &Cell::from(5), false, 4, RunGuard::default());
+    assert_eq!(result.matches, vec![(0, 5), (1, 4), (2, 3), (3, 2), (4, 1), (5, 0), (6, -1), (7, -2)]);
+    assert_eq!(result.runs, 10 * 12);
+    assert_eq!(result.failures, 0);
+}
+
+#[test]
+fn test_search_stops_at_first() {
+    let instructions = day_2_input();
+
+    let result = search(&instructions, 0..=151, 0..=151, &Cell::from(19690720), true, 4, RunGuard::default());
+    assert_eq!(result.matches, vec![(82, 50)]);
+    assert!(result.runs < 152 * 152);
+
+    let result = search(&instructions, 0..=9, 0..=9, &Cell::from(-1), true, 2, RunGuard::default());
+    assert_eq!(result, SearchResult { runs: 100, ..SearchResult::default() });
+}
+
+#[test]
+fn test_search_guards_runs() {
+    // A noun of 1 jumps to the verb, where 0 loops forever, 1 and 2 fall
+    // into unknown opcodes, and 3 halts.
+    //
+    let instructions = cell::from_slice(&[1105,0,0,99]);
+    let matches = vec![(0, 0), (0, 1), (0, 2), (0, 3), (1, 3)];
+
+    let guard = RunGuard { step_limit: Some(5), detect_loops: true };
+    let result = search(&instructions, 0..=1, 0..=3, &Cell::from(1105), false, 2, guard);
+    assert_eq!(result, SearchResult { matches: matches.clone(), runs: 8, failures: 2, loops: 1, step_limits: 0 });
+
+    let guard = RunGuard { step_limit: Some(5), detect_loops: false };
+    let result = search(&instructions, 0..=1, 0..=3, &Cell::from(1105), false, 2, guard);
+    assert_eq!(result, SearchResult { matches, runs: 8, failures: 2, loops: 0, step_limits: 1 });
+}
@@ -0,0 +1,203 @@
+
+/*!
+Chains of amplifiers, each running its own copy of one Intcode program.
+
+Every amplifier is given its phase setting as its first input, then the
+signal from the amplifier before it, starting from 0 for the first one.
+The last amplifier's output is the chain's signal.
+
+```text
+0 -> [A] -> [B] -> [C] -> [D] -> [E] -> signal
+```
+
+In a feedback loop, the last amplifier's output is fed back into the first,
+and the signal goes round until the amplifiers halt. The chain's signal is
+then the last value the final amplifier produced.
+*/
+
+use std::fmt;
+
+use crate::cell::Cell;
+use crate::error::ExecError;
+use crate::{ProgramState, RunStatus};
+
+/// Why a chain of amplifiers couldn't produce a signal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AmplifierError {
+
+    // The amplifier at the index failed to execute.
+    //
+    Exec { amplifier: usize, error: ExecError },
+
+    // The amplifier at the index asked for more input than the chain gives it.
+    //
+    Stalled(usize),
+
+    // The chain halted before the last amplifier produced anything.
+    //
+    NoSignal,
+
+    // No phase settings were given, so there are no amplifiers to run.
+    //
+    NoAmplifiers,
+}
+
+impl fmt::Display for AmplifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmplifierError::Exec { amplifier, error } => write!(f, "amplifier {} failed: {}", amplifier, error),
+            AmplifierError::Stalled(amplifier) => write!(f, "amplifier {} is waiting for input", amplifier),
+            AmplifierError::NoSignal => write!(f, "the chain produced no signal"),
+            AmplifierError::NoAmplifiers => write!(f, "no phase settings to run amplifiers with"),
+        }
+    }
+}
+
+impl std::error::Error for AmplifierError {}
+
+/// Run one amplifier per phase setting, in order, and return the final signal.
+pub fn run_chain(program: &[Cell], phases: &[Cell], feedback: bool) -> Result<Cell, AmplifierError> {
+
+    // An empty feedback loop would go round forever without running anything.
+    //
+    if phases.is_empty() {
+        return Err(AmplifierError::NoAmplifiers);
+    }
+
+    // Each amplifier gets its own memory, loaded from the same image.
+    //
+    let mut amplifiers: Vec<ProgramState> = phases.iter()
+        .map(|phase| {
+            let mut amplifier = ProgramState::from_vec(program.to_vec());
+            amplifier.push_input(phase.to_owned());
+            amplifier
+        })
+        .collect();
+
+    let mut signal = Cell::from(0);
+    let mut last_signal = None;
+
+    loop {
+        for (index, amplifier) in amplifiers.iter_mut().enumerate() {
+            amplifier.push_input(signal);
+
+            signal = match amplifier.run() {
+                Ok(RunStatus::Output(value)) => value,
+                Ok(RunStatus::Halted) => return last_signal.ok_or(AmplifierError::NoSignal),
+                Ok(RunStatus::NeedsInput) => return Err(AmplifierError::Stalled(index)),
+                Err(error) => return Err(AmplifierError::Exec { amplifier: index, error }),
+            };
+        }
+
+        if !feedback {
+            return Ok(signal);
+        }
+
+        last_signal = Some(signal.to_owned());
+    }
+}
+
+/// Try every ordering of `phases`, returning the ordering that gives the
+/// largest signal along with that signal.
+pub fn best_phase_settings(program: &[Cell], phases: &[Cell], feedback: bool) -> Result<(Vec<Cell>, Cell), AmplifierError> {
+    if phases.is_empty() {
+        return Err(AmplifierError::NoAmplifiers);
+    }
+
+    let mut best: Option<(Vec<Cell>, Cell)> = None;
+
+    for permutation in permutations(phases) {
+        let signal = run_chain(program, &permutation, feedback)?;
+
+        if best.as_ref().is_none_or(|(_, best_signal)| signal > *best_signal) {
+            best = Some((permutation, signal));
+        }
+    }
+
+    best.ok_or(AmplifierError::NoSignal)
+}
+
+/// Every ordering of `values`, generated with Heap's algorithm.
+fn permutations(values: &[Cell]) -> Vec<Vec<Cell>> {
+    let mut current = values.to_vec();
+    let mut counters = vec![0; current.len()];
+    let mut result = vec![current.clone()];
+
+    let mut index = 1;
+    while index < current.len() {
+        if counters[index] < index {
+            let other = if index % 2 == 0 { 0 } else { counters[index] };
+            current.swap(other, index);
+            result.push(current.clone());
+
+            counters[index] += 1;
+            index = 1;
+        } else {
+            counters[index] = 0;
+            index += 1;
+        }
+    }
+
+    result
+}
+
+#[test]
+fn test_permutations() {
+    let mut all = permutations(&crate::cell::from_slice(&[1, 2, 3]));
+    all.sort();
+
+    let expected: Vec<Vec<Cell>> = [[1,2,3], [1,3,2], [2,1,3], [2,3,1], [3,1,2], [3,2,1]].iter()
+        .map(|values| crate::cell::from_slice(values))
+        .collect();
+
+    assert_eq!(all, expected);
+}
+
+#[test]
+fn test_amplifier_chain_samples() {
+    use crate::cell::from_slice;
+
+    let program = from_slice(&[3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0]);
+    assert_eq!(run_chain(&program, &from_slice(&[4,3,2,1,0]), false), Ok(Cell::from(43210)));
+    assert_eq!(best_phase_settings(&program, &from_slice(&[0,1,2,3,4]), false),
+        Ok((from_slice(&[4,3,2,1,0]), Cell::from(43210))));
+
+    let program = from_slice(&[3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0]);
+    assert_eq!(best_phase_settings(&program, &from_slice(&[0,1,2,3,4]), false),
+        Ok((from_slice(&[0,1,2,3,4]), Cell::from(54321))));
+}
+
+#[test]
+fn test_amplifier_feedback_loop_sample() {
+    use crate::cell::from_slice;
+
+    let program = from_slice(&[
+        3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5,
+    ]);
+
+    assert_eq!(run_chain(&program, &from_slice(&[9,8,7,6,5]), true), Ok(Cell::from(139629729)));
+    assert_eq!(best_phase_settings(&program, &from_slice(&[5,6,7,8,9]), true),
+        Ok((from_slice(&[9,8,7,6,5]), Cell::from(139629729))));
+}
+
+#[test]
+fn test_amplifier_errors() {
+    use crate::cell::from_slice;
+
+    // Reads three inputs, but the chain only gives two.
+    //
+    let program = from_slice(&[3,0,3,0,3,0,99]);
+    assert_eq!(run_chain(&program, &from_slice(&[0]), false), Err(AmplifierError::Stalled(0)));
+
+    // Halts without any output.
+    //
+    let program = from_slice(&[3,0,3,0,99]);
+    assert_eq!(run_chain(&program, &from_slice(&[0, 1]), true), Err(AmplifierError::NoSignal));
+
+    // No amplifiers at all, with or without feedback.
+    //
+    let program = from_slice(&[99]);
+    assert_eq!(run_chain(&program, &[], true), Err(AmplifierError::NoAmplifiers));
+    assert_eq!(run_chain(&program, &[], false), Err(AmplifierError::NoAmplifiers));
+    assert_eq!(best_phase_settings(&program, &[], true), Err(AmplifierError::NoAmplifiers));
+}
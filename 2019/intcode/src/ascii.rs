@@ -0,0 +1,182 @@
+
+/*!
+Talking to Intcode programs that use ASCII for their input and output.
+
+Output values up to 127 are printed as the characters they encode. Anything
+larger, or negative, can't be a character, so it is printed as a number on a
+line of its own; puzzles use this for their final answer. Each line of input
+is sent one character at a time, followed by a newline (10).
+
+A session runs the program until it waits for input, prints what it said,
+and then sends it the next line. Lines come either from the terminal, or
+from a transcript file played back with each line echoed as if typed.
+*/
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::cell::{self, Cell};
+use crate::error::ExecError;
+use crate::{ProgramState, RunStatus};
+
+/// The largest output value that is printed as a character.
+const MAX_ASCII: usize = 127;
+
+/// How a session came to an end.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEnd {
+
+    // The program halted.
+    //
+    Halted,
+
+    // The program asked for input after the last line was sent.
+    //
+    OutOfInput,
+}
+
+#[derive(Debug)]
+pub enum AsciiError {
+
+    // The program failed to execute.
+    //
+    Exec(ExecError),
+
+    // The program's text couldn't be written out.
+    //
+    Io(io::Error),
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiError::Exec(error) => write!(f, "execution failed: {}", error),
+            AsciiError::Io(error) => write!(f, "can't write output: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for AsciiError {}
+
+impl From<io::Error> for AsciiError {
+    fn from(error: io::Error) -> Self {
+        AsciiError::Io(error)
+    }
+}
+
+/// Encode a line of input, including its newline.
+pub fn encode_line(line: &str) -> Vec<Cell> {
+    line.bytes().chain(Some(b'\n')).map(Cell::from).collect()
+}
+
+/// Append the text for one output value to `text`.
+pub fn render(value: &Cell, text: &mut String) {
+    match cell::to_usize(value).filter(|code| *code <= MAX_ASCII) {
+        Some(code) => text.push(code as u8 as char),
+        None => {
+            if !text.is_empty() && !text.ends_with('\n') {
+                text.push('\n');
+            }
+
+            text.push_str(&format!("{}\n", value));
+        }
+    }
+}
+
+/// Run the program until it waits for input or halts, and return the text
+/// it printed along the way.
+fn run_until_input(state: &mut ProgramState) -> Result<(String, bool), ExecError> {
+    let mut text = String::new();
+
+    loop {
+        match state.run()? {
+            RunStatus::Output(value) => render(&value, &mut text),
+            RunStatus::NeedsInput => return Ok((text, false)),
+            RunStatus::Halted => return Ok((text, true)),
+        }
+    }
+}
+
+/// Play a session, sending `lines` to the program one at a time as it
+/// asks for them and writing everything it prints to `out`. With `echo`,
+/// each line sent is written out too, so the result reads like a session
+/// typed at the terminal.
+pub fn play(state: &mut ProgramState, lines: impl IntoIterator<Item = String>, echo: bool, out: &mut impl Write) -> Result<SessionEnd, AsciiError> {
+    let mut lines = lines.into_iter();
+
+    loop {
+        let (text, halted) = run_until_input(state).map_err(AsciiError::Exec)?;
+        out.write_all(text.as_bytes())?;
+        out.flush()?;
+
+        if halted {
+            return Ok(SessionEnd::Halted);
+        }
+
+        let line = match lines.next() {
+            Some(line) => line,
+            None => return Ok(SessionEnd::OutOfInput),
+        };
+
+        if echo {
+            writeln!(out, "{}", line)?;
+        }
+
+        for value in encode_line(&line) {
+            state.push_input(value);
+        }
+    }
+}
+
+/// The lines of a transcript, without comment lines starting with `#`.
+pub fn transcript_lines(transcript: &str) -> Vec<String> {
+    transcript.lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect()
+}
+
+/// Echo every line back, then output a number too large to be a character.
+#[cfg(test)]
+const ECHO: &str = "
+    loop:   IN [c]
+            OUT [c]
+            EQ [c], 10, [done]
+            JZ [done], loop
+            OUT 1000
+            HLT
+    c:      DATA 0
+    done:   DATA 0
+";
+
+#[test]
+fn test_ascii_encoding() {
+    assert_eq!(encode_line("Hi"), crate::cell::from_slice(&[72, 105, 10]));
+
+    let mut text = String::new();
+    for value in crate::cell::from_slice(&[79, 75, 128, 33, -1]) {
+        render(&value, &mut text);
+    }
+
+    assert_eq!(text, "OK\n128\n!\n-1\n");
+}
+
+#[test]
+fn test_ascii_play() {
+    let program = crate::assembler::assemble(ECHO).unwrap();
+
+    let mut out = Vec::new();
+    let mut state = ProgramState::from_vec(program.to_vec());
+    assert_eq!(play(&mut state, vec!["hello".to_string()], false, &mut out).unwrap(), SessionEnd::Halted);
+    assert_eq!(String::from_utf8(out).unwrap(), "hello\n1000\n");
+
+    let mut out = Vec::new();
+    let mut state = ProgramState::from_vec(program.to_vec());
+    assert_eq!(play(&mut state, transcript_lines("# Say hi\nhi\n"), true, &mut out).unwrap(), SessionEnd::Halted);
+    assert_eq!(String::from_utf8(out).unwrap(), "hi\nhi\n1000\n");
+
+    let mut out = Vec::new();
+    let mut state = ProgramState::from_vec(program);
+    assert_eq!(play(&mut state, Vec::new(), true, &mut out).unwrap(), SessionEnd::OutOfInput);
+    assert!(out.is_empty());
+}
@@ -0,0 +1,407 @@
+
+/*!
+Assembler for a small Intcode assembly language.
+
+Each line holds an optional `label:`, followed by an instruction or a `DATA`
+directive, and an optional `#` comment. Mnemonics and operands use the same
+syntax as the disassembler listing:
+
+```text
+# Output the sum of two numbers.
+start:  ADD [lhs], [rhs], [sum]
+        OUT [sum]
+        HLT
+lhs:    DATA 30
+rhs:    DATA 12
+sum:    DATA 0
+```
+
+Operands are `[x]` for position mode, `x` for immediate mode, and `[rb+x]`
+or `[rb-x]` for relative mode. Each value can be a number, a label, or a sum
+like `label+1`. `DATA` takes a comma separated list of values.
+*/
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cell::{self, Cell};
+use crate::{lookup_opcode, written_parameter, OpCode, ParameterMode, OPCODES};
+
+/// The kind of problem found in the assembly source.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssembleErrorKind {
+
+    // The mnemonic doesn't name a supported opcode.
+    //
+    UnknownMnemonic(String),
+
+    // The instruction has the wrong number of operands.
+    //
+    OperandCount { mnemonic: String, expected: usize, found: usize },
+
+    // An operand or value couldn't be parsed.
+    //
+    BadOperand(String),
+
+    // A label isn't a valid identifier.
+    //
+    BadLabel(String),
+
+    // A label is defined more than once.
+    //
+    DuplicateLabel(String),
+
+    // A label is used but never defined.
+    //
+    UndefinedLabel(String),
+
+    // The operand (1 based) an instruction writes to is immediate, which
+    // the interpreter would reject.
+    //
+    ImmediateWrite { mnemonic: String, operand: usize },
+}
+
+impl fmt::Display for AssembleErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleErrorKind::UnknownMnemonic(mnemonic) => write!(f, "unknown mnemonic '{}'", mnemonic),
+            AssembleErrorKind::OperandCount { mnemonic, expected, found } => {
+                write!(f, "{} expects {} operands, found {}", mnemonic, expected, found)
+            }
+            AssembleErrorKind::BadOperand(operand) => write!(f, "bad operand '{}'", operand),
+            AssembleErrorKind::BadLabel(label) => write!(f, "bad label '{}'", label),
+            AssembleErrorKind::DuplicateLabel(label) => write!(f, "label '{}' is already defined", label),
+            AssembleErrorKind::UndefinedLabel(label) => write!(f, "label '{}' is not defined", label),
+            AssembleErrorKind::ImmediateWrite { mnemonic, operand } => {
+                write!(f, "{} writes to operand {}, which can't be immediate", mnemonic, operand)
+            }
+        }
+    }
+}
+
+/// An error in the assembly source, along with its 1 based line number.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub kind: AssembleErrorKind,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+/// A parsed, but not yet resolved, source line.
+enum Statement<'s> {
+    Instruction { opcode: u32, mnemonic: &'static str, operands: Vec<&'s str> },
+    Data(Vec<&'s str>),
+}
+
+struct SourceLine<'s> {
+    line: usize,
+    statement: Statement<'s>,
+}
+
+impl Statement<'_> {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => 1 + operands.len(),
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn split_list(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    text.split(',').map(str::trim).collect()
+}
+
+fn find_opcode(mnemonic: &str) -> Option<(u32, &'static dyn OpCode)> {
+    OPCODES.iter()
+        .filter_map(|code| lookup_opcode(*code).ok().map(|op_code| (*code, op_code)))
+        .find(|(_, op_code)| op_code.mnemonic().eq_ignore_ascii_case(mnemonic))
+}
+
+/// Evaluate a value like `12`, `-3`, `label` or `label+1`.
+fn evaluate(text: &str, labels: &HashMap<&str, usize>) -> Result<i64, AssembleErrorKind> {
+    let bad_operand = || AssembleErrorKind::BadOperand(text.to_string());
+
+    let mut total: i64 = 0;
+    let mut term_start = 0;
+    let bytes = text.as_bytes();
+
+    // Split into signed terms, a sign at the very start belongs to the first term.
+    //
+    for index in 1..=bytes.len() {
+        if index < bytes.len() && bytes[index] != b'+' && bytes[index] != b'-' {
+            continue;
+        }
+
+        let term = text[term_start..index].trim();
+        let (negative, term) = match term.strip_prefix('-') {
+            Some(rest) => (true, rest.trim()),
+            None => (false, term.strip_prefix('+').unwrap_or(term).trim()),
+        };
+
+        let value = if is_label(term) {
+            match labels.get(term) {
+                Some(address) => *address as i64,
+                None => return Err(AssembleErrorKind::UndefinedLabel(term.to_string())),
+            }
+        } else {
+            term.parse::<i64>().map_err(|_| bad_operand())?
+        };
+
+        let value = if negative { value.checked_neg() } else { Some(value) };
+        total = value.and_then(|value| total.checked_add(value)).ok_or_else(bad_operand)?;
+        term_start = index;
+    }
+
+    Ok(total)
+}
+
+/// Resolve an operand into its mode and value.
+fn resolve_operand(text: &str, labels: &HashMap<&str, usize>) -> Result<(ParameterMode, i64), AssembleErrorKind> {
+    match text.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        Some(inner) => {
+            let inner = inner.trim();
+
+            match inner.strip_prefix("rb") {
+                Some("") => Ok((ParameterMode::Relative, 0)),
+                Some(offset) if offset.starts_with(['+', '-']) => {
+                    Ok((ParameterMode::Relative, evaluate(offset, labels)?))
+                }
+                _ => Ok((ParameterMode::Position, evaluate(inner, labels)?)),
+            }
+        }
+        None => Ok((ParameterMode::Immediate, evaluate(text, labels)?)),
+    }
+}
+
+fn mode_digit(mode: ParameterMode) -> i64 {
+    match mode {
+        ParameterMode::Position => 0,
+        ParameterMode::Immediate => 1,
+        ParameterMode::Relative => 2,
+    }
+}
+
+/// Assemble `source` into a program image.
+pub fn assemble(source: &str) -> Result<Vec<Cell>, AssembleError> {
+
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    // First pass: parse every line and assign each label its address.
+    //
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |kind| AssembleError { line, kind };
+
+        let mut text = text.split('#').next().unwrap_or("").trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(error(AssembleErrorKind::BadLabel(label.to_string())));
+            }
+
+            if labels.insert(label, address).is_some() {
+                return Err(error(AssembleErrorKind::DuplicateLabel(label.to_string())));
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], text[split..].trim()),
+            None => (text, ""),
+        };
+
+        let statement = if mnemonic.eq_ignore_ascii_case("DATA") {
+            Statement::Data(split_list(rest))
+        } else {
+            let (opcode, op_code) = find_opcode(mnemonic)
+                .ok_or_else(|| error(AssembleErrorKind::UnknownMnemonic(mnemonic.to_string())))?;
+
+            let operands = split_list(rest);
+            if operands.len() != op_code.parameter_count() {
+                return Err(error(AssembleErrorKind::OperandCount {
+                    mnemonic: op_code.mnemonic().to_string(),
+                    expected: op_code.parameter_count(),
+                    found: operands.len(),
+                }));
+            }
+
+            Statement::Instruction { opcode, mnemonic: op_code.mnemonic(), operands }
+        };
+
+        address += statement.size();
+        statements.push(SourceLine { line, statement });
+    }
+
+    // Second pass: resolve operands now that every label is known.
+    //
+    let mut image = Vec::with_capacity(address);
+
+    for SourceLine { line, statement } in statements {
+        let error = |kind| AssembleError { line, kind };
+
+        match statement {
+            Statement::Instruction { opcode, mnemonic, operands } => {
+                let mut value = i64::from(opcode);
+                let mut parameters = Vec::with_capacity(operands.len());
+                let mut place = 100;
+
+                for (index, operand) in operands.into_iter().enumerate() {
+                    let (mode, parameter) = resolve_operand(operand, &labels).map_err(error)?;
+
+                    if mode == ParameterMode::Immediate && written_parameter(opcode) == Some(index + 1) {
+                        return Err(error(AssembleErrorKind::ImmediateWrite { mnemonic: mnemonic.to_string(), operand: index + 1 }));
+                    }
+
+                    value += mode_digit(mode) * place;
+                    place *= 10;
+                    parameters.push(parameter);
+                }
+
+                image.push(cell::from_i64(value));
+                image.extend(parameters.into_iter().map(cell::from_i64));
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    image.push(cell::from_i64(evaluate(value, &labels).map_err(error)?));
+                }
+            }
+        }
+    }
+
+    Ok(image)
+}
+
+/// Format a program image in the comma separated form programs are loaded from.
+pub fn to_program_text(image: &[Cell]) -> String {
+    let values: Vec<String> = image.iter().map(|value| value.to_string()).collect();
+    values.join(",")
+}
+
+#[test]
+fn test_assemble_sample() {
+    let source = "
+        # The day 2 sample program.
+                ADD [nine], [ten], [3]
+                MUL [3], [eleven], [0]
+                HLT
+        nine:   DATA 30
+        ten:    DATA 40
+        eleven: DATA 50
+    ";
+
+    let image = assemble(source).unwrap();
+    assert_eq!(to_program_text(&image), "1,9,10,3,2,3,11,0,99,30,40,50");
+}
+
+#[test]
+fn test_assemble_modes_and_expressions() {
+    let source = "
+        start:  mul [end+1], 3, [end+1]   # lowercase mnemonics are fine
+                arb -1
+                OUT [rb+1]
+                OUT [rb-2]
+                OUT [rb]
+                JNZ 1, start
+        end:    DATA 99, 33
+    ";
+
+    let image = assemble(source).unwrap();
+    assert_eq!(to_program_text(&image), "1002,16,3,16,109,-1,204,1,204,-2,204,0,1105,1,0,99,33");
+}
+
+#[test]
+fn test_assemble_runs() {
+    // Count down from three, outputting each value.
+    //
+    let source = "
+        loop:   OUT [counter]
+                ADD [counter], -1, [counter]
+                JNZ [counter], loop
+                HLT
+        counter: DATA 3
+    ";
+
+    let image = assemble(source).unwrap();
+    let mut program_state = crate::ProgramState::from_vec(image);
+    program_state.execute().unwrap();
+
+    assert_eq!(program_state.take_output(), crate::cell::from_slice(&[3, 2, 1]));
+}
+
+#[test]
+fn test_assemble_errors() {
+    let error = |source| assemble(source).unwrap_err();
+
+    assert_eq!(error("HLT\nFOO 1"), AssembleError {
+        line: 2,
+        kind: AssembleErrorKind::UnknownMnemonic("FOO".to_string()),
+    });
+
+    assert_eq!(error("\n\nADD 1, 2"), AssembleError {
+        line: 3,
+        kind: AssembleErrorKind::OperandCount { mnemonic: "ADD".to_string(), expected: 3, found: 2 },
+    });
+
+    assert_eq!(error("OUT [1x]"), AssembleError {
+        line: 1,
+        kind: AssembleErrorKind::BadOperand("1x".to_string()),
+    });
+
+    assert_eq!(error("a: HLT\na: HLT"), AssembleError {
+        line: 2,
+        kind: AssembleErrorKind::DuplicateLabel("a".to_string()),
+    });
+
+    assert_eq!(error("JZ 0, nowhere"), AssembleError {
+        line: 1,
+        kind: AssembleErrorKind::UndefinedLabel("nowhere".to_string()),
+    });
+
+    assert_eq!(error("1st: HLT"), AssembleError {
+        line: 1,
+        kind: AssembleErrorKind::BadLabel("1st".to_string()),
+    });
+
+    assert_eq!(error("HLT\nADD 1, 2, 3"), AssembleError {
+        line: 2,
+        kind: AssembleErrorKind::ImmediateWrite { mnemonic: "ADD".to_string(), operand: 3 },
+    });
+
+    assert_eq!(error("IN 5"), AssembleError {
+        line: 1,
+        kind: AssembleErrorKind::ImmediateWrite { mnemonic: "IN".to_string(), operand: 1 },
+    });
+
+    assert_eq!(error("OUT [1x]").to_string(), "line 1: bad operand '1x'");
+    assert_eq!(error("EQ 1, 2, 3").to_string(), "line 1: EQ writes to operand 3, which can't be immediate");
+}
@@ -0,0 +1,152 @@
+
+/*!
+The value held by a single Intcode memory cell.
+
+Cells are signed 64-bit integers by default. Building with the `bigint`
+feature switches them to arbitrary-precision integers, in which case the
+checked arithmetic below can never overflow.
+*/
+
+#[cfg(not(feature = "bigint"))]
+mod repr {
+
+    use std::convert::TryFrom;
+
+    pub type Cell = i64;
+
+    pub fn from_i64(value: i64) -> Cell {
+        value
+    }
+
+    pub fn to_i64(value: &Cell) -> Option<i64> {
+        Some(*value)
+    }
+
+    pub fn to_usize(value: &Cell) -> Option<usize> {
+        usize::try_from(*value).ok()
+    }
+
+    /// Convert an unsigned value, saturating at the largest cell.
+    pub fn from_u128(value: u128) -> Cell {
+        Cell::try_from(value).unwrap_or(Cell::MAX)
+    }
+
+    pub fn checked_add(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        lhs.checked_add(*rhs)
+    }
+
+    pub fn to_hex(value: &Cell) -> String {
+        if *value < 0 {
+            format!("-{:x}", value.unsigned_abs())
+        } else {
+            format!("{:x}", value)
+        }
+    }
+
+    pub fn checked_mul(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        lhs.checked_mul(*rhs)
+    }
+
+    pub fn checked_sub(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        lhs.checked_sub(*rhs)
+    }
+
+    /// Divide when `rhs` divides `lhs` exactly, with no remainder.
+    pub fn exact_div(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        match lhs.checked_rem(*rhs)? {
+            0 => lhs.checked_div(*rhs),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "bigint")]
+mod repr {
+
+    use num_traits::{ToPrimitive, Zero};
+
+    pub type Cell = num_bigint::BigInt;
+
+    pub fn from_i64(value: i64) -> Cell {
+        Cell::from(value)
+    }
+
+    pub fn to_i64(value: &Cell) -> Option<i64> {
+        value.to_i64()
+    }
+
+    pub fn to_usize(value: &Cell) -> Option<usize> {
+        value.to_usize()
+    }
+
+    pub fn from_u128(value: u128) -> Cell {
+        Cell::from(value)
+    }
+
+    pub fn checked_add(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        Some(lhs + rhs)
+    }
+
+    pub fn to_hex(value: &Cell) -> String {
+        format!("{:x}", value)
+    }
+
+    pub fn checked_mul(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        Some(lhs * rhs)
+    }
+
+    pub fn checked_sub(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        Some(lhs - rhs)
+    }
+
+    /// Divide when `rhs` divides `lhs` exactly, with no remainder.
+    pub fn exact_div(lhs: &Cell, rhs: &Cell) -> Option<Cell> {
+        if rhs.is_zero() || !(lhs % rhs).is_zero() {
+            return None;
+        }
+
+        Some(lhs / rhs)
+    }
+}
+
+pub use repr::*;
+
+/// Convert a slice of plain integers into cells.
+pub fn from_slice(values: &[i64]) -> Vec<Cell> {
+    values.iter().map(|value| from_i64(*value)).collect()
+}
+
+#[test]
+fn test_cell_conversions() {
+    assert_eq!(to_usize(&Cell::from(42)), Some(42));
+    assert_eq!(to_usize(&Cell::from(-1)), None);
+    assert_eq!(to_i64(&Cell::from(-1)), Some(-1));
+    assert_eq!(to_hex(&Cell::from(255)), "ff");
+    assert_eq!(to_hex(&Cell::from(-255)), "-ff");
+}
+
+#[test]
+fn test_cell_checked_arithmetic() {
+    assert_eq!(checked_add(&Cell::from(-2), &Cell::from(5)), Some(Cell::from(3)));
+    assert_eq!(checked_mul(&Cell::from(-2), &Cell::from(5)), Some(Cell::from(-10)));
+    assert_eq!(checked_sub(&Cell::from(-2), &Cell::from(5)), Some(Cell::from(-7)));
+    assert_eq!(exact_div(&Cell::from(-10), &Cell::from(5)), Some(Cell::from(-2)));
+    assert_eq!(exact_div(&Cell::from(10), &Cell::from(4)), None);
+    assert_eq!(exact_div(&Cell::from(10), &Cell::from(0)), None);
+}
+
+#[test]
+#[cfg(not(feature = "bigint"))]
+fn test_cell_checked_arithmetic_overflow() {
+    assert_eq!(checked_add(&i64::MAX, &1), None);
+    assert_eq!(checked_mul(&i64::MAX, &2), None);
+    assert_eq!(checked_sub(&i64::MIN, &1), None);
+    assert_eq!(exact_div(&i64::MIN, &-1), None);
+}
+
+#[test]
+#[cfg(feature = "bigint")]
+fn test_cell_checked_arithmetic_no_overflow() {
+    let max = Cell::from(i64::MAX);
+    assert_eq!(checked_mul(&max, &max), Some(Cell::from(i128::from(i64::MAX) * i128::from(i64::MAX))));
+}
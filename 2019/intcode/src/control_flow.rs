@@ -0,0 +1,403 @@
+
+/*!
+Static control-flow analysis of an Intcode image.
+
+Instructions are decoded from an entry point, following both sides of every
+jump whose target is an immediate value. A jump with an immediate condition
+only goes one way, so `JZ 0, loop` is treated as an unconditional jump, and a
+jump that can never be taken as an ordinary instruction. Targets read from
+memory or relative to the relative base are only known at runtime, so those
+jumps are marked as indirect and not followed.
+
+The reachable instructions are split into basic blocks, each starting at the
+entry point, a jump target, or the instruction after a jump, and together
+they form the control-flow graph. It can be exported as Graphviz DOT:
+
+```text
+$ cargo run cfg part-one-input.txt | dot -Tsvg > program.svg
+```
+
+The image isn't executed, so writes whose address is a position mode
+parameter are the only ones known. Any that land on a cell of a reachable
+instruction make the program self-modifying there, and are reported and
+drawn as dashed red edges.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::cell::{self, Cell};
+use crate::disassembler::{self, ListingLine};
+use crate::{written_parameter, ParameterMode};
+
+/// How control leaves a basic block.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+
+    // Runs on into the next block, which starts at the address because
+    // something else jumps there too.
+    //
+    Fallthrough(usize),
+
+    // A conditional jump to `taken`, which otherwise carries on at `next`.
+    //
+    Branch { taken: usize, next: usize },
+
+    // A jump whose condition is an immediate value that always takes it.
+    //
+    Jump(usize),
+
+    // A jump to an address only known at runtime, which may carry on at
+    // `next` when it isn't taken.
+    //
+    Indirect { next: Option<usize> },
+
+    // The halt opcode.
+    //
+    Halt,
+}
+
+/// A run of instructions that always execute together, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    pub lines: Vec<ListingLine>,
+    pub terminator: Terminator,
+}
+
+impl BasicBlock {
+
+    /// The addresses control can go to from the end of the block.
+    pub fn successors(&self) -> Vec<usize> {
+        match self.terminator {
+            Terminator::Fallthrough(next) | Terminator::Jump(next) => vec![next],
+            Terminator::Branch { taken, next } => vec![taken, next],
+            Terminator::Indirect { next } => next.into_iter().collect(),
+            Terminator::Halt => Vec::new(),
+        }
+    }
+}
+
+/// A write by the instruction at `instruction` into the cell at `target`,
+/// which is part of a reachable instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfModifyingWrite {
+    pub instruction: usize,
+    pub target: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlFlowGraph {
+    pub entry: usize,
+
+    // Every reachable block, by its start address.
+    //
+    pub blocks: BTreeMap<usize, BasicBlock>,
+
+    // Addresses control can reach that don't hold a complete instruction.
+    //
+    pub invalid: BTreeSet<usize>,
+
+    pub self_modifying: Vec<SelfModifyingWrite>,
+}
+
+/// Where control may go after a single instruction.
+enum Flow {
+    Next,
+    Branch(usize),
+    Jump(usize),
+    Indirect { falls_through: bool },
+    Halt,
+}
+
+fn immediate(line: &ListingLine, index: usize) -> Option<&Cell> {
+    let operand = &line.operands[index];
+
+    match operand.mode {
+        ParameterMode::Immediate => Some(&operand.value),
+        _ => None,
+    }
+}
+
+/// Work out where control goes after the instruction on `line`.
+fn flow(line: &ListingLine) -> Flow {
+    let jump_if_true = match line.opcode {
+        Some(99) => return Flow::Halt,
+        Some(5) => true,
+        Some(6) => false,
+        _ => return Flow::Next,
+    };
+
+    let always = match immediate(line, 0) {
+        Some(condition) if (*condition != Cell::from(0)) != jump_if_true => return Flow::Next,
+        Some(_) => true,
+        None => false,
+    };
+
+    // A negative target is only a problem if the jump is taken, which it
+    // might never be, so it is left for the runtime to report.
+    //
+    let target = match immediate(line, 1).and_then(cell::to_usize) {
+        Some(target) => target,
+        None => return Flow::Indirect { falls_through: !always },
+    };
+
+    if always { Flow::Jump(target) } else { Flow::Branch(target) }
+}
+
+impl ControlFlowGraph {
+
+    /// Analyse the image starting from the instruction at `entry`.
+    pub fn build(image: &[Cell], entry: usize) -> Self {
+        let mut lines: BTreeMap<usize, ListingLine> = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut invalid = BTreeSet::new();
+        let mut pending = vec![entry];
+
+        leaders.insert(entry);
+
+        // Find every reachable instruction, and where each block starts.
+        //
+        while let Some(mut address) = pending.pop() {
+            loop {
+
+                // Joining code that was already found makes that a block start.
+                //
+                if lines.contains_key(&address) {
+                    leaders.insert(address);
+                    break;
+                }
+
+                let line = match disassembler::decode_at(image, address) {
+                    Some(line) => line,
+                    None => {
+                        invalid.insert(address);
+                        break;
+                    }
+                };
+
+                let next = address + line.cells.len();
+                let flow = flow(&line);
+                lines.insert(address, line);
+
+                match flow {
+                    Flow::Next => address = next,
+                    Flow::Halt | Flow::Indirect { falls_through: false } => break,
+                    Flow::Indirect { falls_through: true } => {
+                        leaders.insert(next);
+                        address = next;
+                    }
+                    Flow::Jump(target) => {
+                        leaders.insert(target);
+                        pending.push(target);
+                        break;
+                    }
+                    Flow::Branch(target) => {
+                        leaders.insert(target);
+                        leaders.insert(next);
+                        pending.push(target);
+                        address = next;
+                    }
+                }
+            }
+        }
+
+        // Cut the instructions into blocks at each leader.
+        //
+        let mut blocks = BTreeMap::new();
+
+        for start in leaders.iter().copied().filter(|start| lines.contains_key(start)) {
+            let mut block_lines = Vec::new();
+            let mut address = start;
+
+            let terminator = loop {
+                let line = lines[&address].clone();
+                let next = address + line.cells.len();
+                let flow = flow(&line);
+                block_lines.push(line);
+
+                match flow {
+                    Flow::Halt => break Terminator::Halt,
+                    Flow::Jump(target) => break Terminator::Jump(target),
+                    Flow::Branch(target) => break Terminator::Branch { taken: target, next },
+                    Flow::Indirect { falls_through } => break Terminator::Indirect { next: Some(next).filter(|_| falls_through) },
+                    Flow::Next if leaders.contains(&next) || !lines.contains_key(&next) => break Terminator::Fallthrough(next),
+                    Flow::Next => address = next,
+                }
+            };
+
+            blocks.insert(start, BasicBlock { start, lines: block_lines, terminator });
+        }
+
+        // Look for writes into the cells of reachable instructions.
+        //
+        let code: BTreeSet<usize> = lines.values()
+            .flat_map(|line| line.address..line.address + line.cells.len())
+            .collect();
+
+        let self_modifying = lines.values()
+            .filter_map(|line| {
+                let operand = &line.operands[written_parameter(line.opcode?)? - 1];
+                let target = match operand.mode {
+                    ParameterMode::Position => cell::to_usize(&operand.value)?,
+                    _ => return None,
+                };
+
+                Some(SelfModifyingWrite { instruction: line.address, target }).filter(|_| code.contains(&target))
+            })
+            .collect();
+
+        Self { entry, blocks, invalid, self_modifying }
+    }
+
+    /// The block holding the instruction that covers `address`.
+    pub fn block_containing(&self, address: usize) -> Option<&BasicBlock> {
+        let (_, block) = self.blocks.range(..=address).next_back()?;
+        let last = block.lines.last()?;
+
+        Some(block).filter(|_| address < last.address + last.cells.len())
+    }
+
+    /// Render the graph in Graphviz DOT, with each block listing its
+    /// instructions and self-modifying writes drawn as dashed red edges.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        let escape = |text: String| text.replace('\\', "\\\\").replace('"', "\\\"");
+
+        writeln!(dot, "digraph intcode {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        writeln!(dot, "    entry [shape=point];").unwrap();
+        writeln!(dot, "    entry -> b{};", self.entry).unwrap();
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for line in &block.lines {
+                label.push_str(&escape(line.to_string()));
+                if self.self_modifying.iter().any(|write| write.instruction == line.address) {
+                    label.push_str("  (writes code)");
+                }
+
+                label.push_str("\\l");
+            }
+
+            let modified = self.self_modifying.iter().any(|write| self.block_containing(write.target) == Some(block));
+            let style = if modified { ", color=red" } else { "" };
+            writeln!(dot, "    b{} [label=\"{}\"{}];", block.start, label, style).unwrap();
+        }
+
+        let mut missing = BTreeSet::new();
+        for block in self.blocks.values() {
+            let labels: Vec<(usize, &str)> = match block.terminator {
+                Terminator::Branch { taken, next } => vec![(taken, "taken"), (next, "not taken")],
+                _ => block.successors().into_iter().map(|next| (next, "")).collect(),
+            };
+
+            for (next, label) in labels {
+                if !self.blocks.contains_key(&next) {
+                    missing.insert(next);
+                }
+
+                match label {
+                    "" => writeln!(dot, "    b{} -> b{};", block.start, next).unwrap(),
+                    _ => writeln!(dot, "    b{} -> b{} [label=\"{}\"];", block.start, next, label).unwrap(),
+                }
+            }
+
+            if let Terminator::Indirect { .. } = block.terminator {
+                writeln!(dot, "    b{} -> indirect [style=dotted];", block.start).unwrap();
+            }
+        }
+
+        if self.blocks.values().any(|block| matches!(block.terminator, Terminator::Indirect { .. })) {
+            writeln!(dot, "    indirect [label=\"computed target\", shape=ellipse, style=dotted];").unwrap();
+        }
+
+        for address in missing {
+            writeln!(dot, "    b{} [label=\"{}: not code\", style=dashed];", address, address).unwrap();
+        }
+
+        // Several instructions in a block writing the same cell share an edge.
+        //
+        let writes: BTreeSet<(usize, usize, usize)> = self.self_modifying.iter()
+            .filter_map(|write| {
+                let from = self.block_containing(write.instruction)?;
+                let to = self.block_containing(write.target)?;
+                Some((from.start, to.start, write.target))
+            })
+            .collect();
+
+        for (from, to, target) in writes {
+            writeln!(dot, "    b{} -> b{} [style=dashed, color=red, label=\"writes [{}]\"];", from, to, target).unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+/// Count down from an input, printing each number.
+#[cfg(test)]
+const COUNTDOWN: &str = "
+            IN [n]
+    loop:   JZ [n], done
+            OUT [n]
+            ADD [n], -1, [n]
+            JZ 0, loop
+    done:   HLT
+    n:      DATA 0
+";
+
+#[test]
+fn test_control_flow_blocks() {
+    let image = crate::assembler::assemble(COUNTDOWN).unwrap();
+    let graph = ControlFlowGraph::build(&image, 0);
+
+    let starts: Vec<usize> = graph.blocks.keys().copied().collect();
+    assert_eq!(starts, vec![0, 2, 5, 14]);
+
+    assert_eq!(graph.blocks[&0].terminator, Terminator::Fallthrough(2));
+    assert_eq!(graph.blocks[&2].terminator, Terminator::Branch { taken: 14, next: 5 });
+    assert_eq!(graph.blocks[&5].terminator, Terminator::Jump(2));
+    assert_eq!(graph.blocks[&5].lines.len(), 3);
+    assert_eq!(graph.blocks[&14].terminator, Terminator::Halt);
+
+    assert!(graph.invalid.is_empty());
+    assert!(graph.self_modifying.is_empty());
+    assert_eq!(graph.block_containing(12).map(|block| block.start), Some(5));
+    assert_eq!(graph.block_containing(15), None);
+}
+
+#[test]
+fn test_control_flow_indirect_and_invalid() {
+    use crate::cell::from_slice;
+
+    // The jump target is read from memory.
+    //
+    let graph = ControlFlowGraph::build(&from_slice(&[105,1,6,99,0,0,3]), 0);
+    assert_eq!(graph.blocks[&0].terminator, Terminator::Indirect { next: None });
+    assert_eq!(graph.blocks.len(), 1);
+
+    // A jump that's never taken, then a branch into data.
+    //
+    let graph = ControlFlowGraph::build(&from_slice(&[1106,1,0,1005,9,8,99,0,42,0]), 0);
+    assert_eq!(graph.blocks[&0].terminator, Terminator::Branch { taken: 8, next: 6 });
+    assert_eq!(graph.blocks[&0].lines.len(), 2);
+    assert_eq!(graph.blocks[&6].terminator, Terminator::Halt);
+    assert_eq!(graph.invalid, [8].iter().copied().collect());
+}
+
+#[test]
+fn test_control_flow_self_modifying_dot() {
+    use crate::cell::from_slice;
+
+    // Overwrite the halt with the sum, 1 + 1 = 2, which multiplies instead.
+    //
+    let graph = ControlFlowGraph::build(&from_slice(&[1101,1,1,4,99]), 0);
+    assert_eq!(graph.self_modifying, vec![SelfModifyingWrite { instruction: 0, target: 4 }]);
+
+    let dot = graph.to_dot();
+    assert!(dot.starts_with("digraph intcode {\n"));
+    assert!(dot.contains("    b0 [label=\"0000  1101,1,1,4          ADD 1, 1, [4]  (writes code)\\l0004  99                  HLT\\l\", color=red];\n"));
+    assert!(dot.contains("    b0 -> b0 [style=dashed, color=red, label=\"writes [4]\"];\n"));
+    assert!(dot.ends_with("}\n"));
+}
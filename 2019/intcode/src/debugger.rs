@@ -0,0 +1,510 @@
+
+/*!
+Interactive step debugger for Intcode programs.
+
+The debugger reads one command per line and drives the program a single
+instruction at a time through `ProgramState::step`. An empty line repeats
+the previous command. The machine's undo log is on, so it can also be run
+backwards, to find the instruction that left a bad value in memory.
+
+```text
+step [n]          Execute the next n (default 1) instructions.
+continue          Run until a breakpoint, watchpoint, halt or error.
+back [n]          Undo the last n (default 1) instructions.
+backto addr       Step back to just before the last write to an address.
+rewind            Undo every instruction back to the start of the session.
+break [addr]      Set a breakpoint at an address, or list breakpoints.
+delete addr       Remove a breakpoint.
+watch [addr]      Stop when the value at an address changes, or list watchpoints.
+unwatch addr      Remove a watchpoint.
+regs              Print the instruction pointer, relative base and step count.
+x addr [radius]   Dump memory around an address in decimal.
+xh addr [radius]  Dump memory around an address in hexadecimal.
+list [addr] [n]   Disassemble n (default 5) instructions from an address.
+save file         Save a snapshot of the machine to a file.
+load file         Restore the machine from a snapshot file.
+help              Print this help.
+quit              Leave the debugger.
+```
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::cell::{self, Cell};
+use crate::disassembler;
+use crate::error::ExecError;
+use crate::snapshot::Snapshot;
+use crate::{Outcome, ProgramState};
+
+const HELP: &str = "\
+step [n]          Execute the next n (default 1) instructions.
+continue          Run until a breakpoint, watchpoint, halt or error.
+back [n]          Undo the last n (default 1) instructions.
+backto addr       Step back to just before the last write to an address.
+rewind            Undo every instruction back to the start of the session.
+break [addr]      Set a breakpoint at an address, or list breakpoints.
+delete addr       Remove a breakpoint.
+watch [addr]      Stop when the value at an address changes, or list watchpoints.
+unwatch addr      Remove a watchpoint.
+regs              Print the instruction pointer, relative base and step count.
+x addr [radius]   Dump memory around an address in decimal.
+xh addr [radius]  Dump memory around an address in hexadecimal.
+list [addr] [n]   Disassemble n (default 5) instructions from an address.
+save file         Save a snapshot of the machine to a file.
+load file         Restore the machine from a snapshot file.
+help              Print this help.
+quit              Leave the debugger.
+";
+
+/// The number of cells either side of the address shown by a memory dump.
+const DEFAULT_DUMP_RADIUS: usize = 8;
+
+/// The number of cells shown on each row of a memory dump.
+const DUMP_ROW_LENGTH: usize = 8;
+
+/// Why execution stopped before the requested number of steps.
+#[derive(Debug, PartialEq)]
+enum StopReason {
+    Breakpoint(usize),
+    Watchpoint { address: usize, old: Cell, new: Cell },
+    Halted,
+    Error(ExecError),
+}
+
+/// A step debugger driving a single machine.
+pub struct Debugger<'a> {
+    state: ProgramState<'a>,
+    breakpoints: BTreeSet<usize>,
+
+    // Watched addresses, along with the last value seen at each.
+    //
+    watchpoints: BTreeMap<usize, Cell>,
+}
+
+impl<'a> Debugger<'a> {
+
+    pub fn new(mut state: ProgramState<'a>) -> Self {
+        state.set_undo_log(true);
+
+        Self {
+            state,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    /// Run the command loop until `quit` or the commands run out.
+    pub fn run(&mut self, commands: impl IntoIterator<Item = String>, output: &mut impl Write) -> io::Result<()> {
+
+        writeln!(output, "Intcode debugger, type 'help' for commands.")?;
+        self.show_location(output)?;
+
+        let mut previous = String::new();
+
+        write!(output, "(dbg) ")?;
+        output.flush()?;
+
+        for line in commands {
+            let line = match line.trim() {
+                "" => previous.clone(),
+                line => line.to_string(),
+            };
+
+            if !self.command(&line, output)? {
+                break;
+            }
+
+            previous = line;
+
+            write!(output, "(dbg) ")?;
+            output.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Execute a single command, returning false when the debugger should exit.
+    fn command(&mut self, line: &str, output: &mut impl Write) -> io::Result<bool> {
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let argument = |index: usize| words.get(index).map(|word| word.parse::<usize>());
+
+        match words.first().copied().unwrap_or("") {
+            "s" | "step" => {
+                let count = match argument(1) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => return self.bad_argument(&words, output),
+                };
+
+                for _ in 0..count {
+                    if let Some(reason) = self.step() {
+                        self.report(reason, output)?;
+                        break;
+                    }
+                }
+
+                self.show_location(output)?;
+            }
+            "c" | "continue" => {
+                let reason = loop {
+                    if let Some(reason) = self.step() {
+                        break reason;
+                    }
+                };
+
+                self.report(reason, output)?;
+                self.show_location(output)?;
+            }
+            "back" => {
+                let count = match argument(1) {
+                    None => 1,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => return self.bad_argument(&words, output),
+                };
+
+                let undone = (0..count).take_while(|_| self.state.step_back()).count();
+                if undone < count {
+                    writeln!(output, "Back at the start after undoing {} steps", undone)?;
+                }
+
+                self.refresh_watchpoints();
+                self.show_location(output)?;
+            }
+            "backto" => match argument(1) {
+                Some(Ok(address)) => match self.state.back_to_write(address) {
+                    Some(count) => {
+                        writeln!(output, "Undid {} steps to the last write to [{}]", count, address)?;
+                        self.refresh_watchpoints();
+                        self.show_location(output)?;
+                    }
+                    None => writeln!(output, "No write to [{}] to go back to", address)?,
+                },
+                _ => return self.bad_argument(&words, output),
+            },
+            "rewind" => {
+                let count = self.state.rewind();
+                writeln!(output, "Undid {} steps", count)?;
+                self.refresh_watchpoints();
+                self.show_location(output)?;
+            }
+            "b" | "break" => match argument(1) {
+                None => {
+                    for address in &self.breakpoints {
+                        writeln!(output, "Breakpoint at {:04}", address)?;
+                    }
+                }
+                Some(Ok(address)) => {
+                    self.breakpoints.insert(address);
+                    writeln!(output, "Breakpoint at {:04}", address)?;
+                }
+                Some(Err(_)) => return self.bad_argument(&words, output),
+            },
+            "d" | "delete" => match argument(1) {
+                Some(Ok(address)) if self.breakpoints.remove(&address) => {
+                    writeln!(output, "Deleted breakpoint at {:04}", address)?;
+                }
+                Some(Ok(address)) => writeln!(output, "No breakpoint at {:04}", address)?,
+                _ => return self.bad_argument(&words, output),
+            },
+            "w" | "watch" => match argument(1) {
+                None => {
+                    for (address, value) in &self.watchpoints {
+                        writeln!(output, "Watchpoint at [{}] = {}", address, value)?;
+                    }
+                }
+                Some(Ok(address)) => {
+                    let value = self.state.read(address);
+                    writeln!(output, "Watchpoint at [{}] = {}", address, value)?;
+                    self.watchpoints.insert(address, value);
+                }
+                Some(Err(_)) => return self.bad_argument(&words, output),
+            },
+            "unwatch" => match argument(1) {
+                Some(Ok(address)) if self.watchpoints.remove(&address).is_some() => {
+                    writeln!(output, "Deleted watchpoint at [{}]", address)?;
+                }
+                Some(Ok(address)) => writeln!(output, "No watchpoint at [{}]", address)?,
+                _ => return self.bad_argument(&words, output),
+            },
+            "r" | "regs" => {
+                writeln!(output, "ip={} rb={} steps={}",
+                    self.state.instruction_pointer(), self.state.relative_base(), self.state.steps())?;
+            }
+            command @ "x" | command @ "xh" => {
+                let address = match argument(1) {
+                    Some(Ok(address)) => address,
+                    _ => return self.bad_argument(&words, output),
+                };
+
+                let radius = match argument(2) {
+                    None => DEFAULT_DUMP_RADIUS,
+                    Some(Ok(radius)) => radius,
+                    Some(Err(_)) => return self.bad_argument(&words, output),
+                };
+
+                // The rows shown have to fit in the address space.
+                //
+                if address.checked_add(radius).and_then(|end| end.checked_add(DUMP_ROW_LENGTH)).is_none() {
+                    return self.bad_argument(&words, output);
+                }
+
+                self.dump(address, radius, command == "xh", output)?;
+            }
+            "l" | "list" => {
+                let mut address = match argument(1) {
+                    None => self.state.instruction_pointer(),
+                    Some(Ok(address)) => address,
+                    Some(Err(_)) => return self.bad_argument(&words, output),
+                };
+
+                let count = match argument(2) {
+                    None => 5,
+                    Some(Ok(count)) => count,
+                    Some(Err(_)) => return self.bad_argument(&words, output),
+                };
+
+                for _ in 0..count {
+                    let line = disassembler::decode_memory(self.state.memory(), address);
+                    writeln!(output, "   {}", line)?;
+
+                    // Stop at the top of the address space.
+                    //
+                    address = match address.checked_add(line.cells.len()) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+            }
+            "save" => match words.get(1) {
+                Some(path) => match self.state.snapshot().save(path) {
+                    Ok(()) => writeln!(output, "Saved snapshot to {}", path)?,
+                    Err(error) => writeln!(output, "Failed to save {}: {}", path, error)?,
+                },
+                None => return self.bad_argument(&words, output),
+            },
+            "load" => match words.get(1) {
+                Some(path) => match Snapshot::load(path) {
+                    Ok(snapshot) => {
+                        self.state.restore(snapshot);
+                        self.refresh_watchpoints();
+                        self.show_location(output)?;
+                    }
+                    Err(error) => writeln!(output, "Failed to load {}: {}", path, error)?,
+                },
+                None => return self.bad_argument(&words, output),
+            },
+            "h" | "help" => write!(output, "{}", HELP)?,
+            "q" | "quit" => return Ok(false),
+            command => writeln!(output, "Unknown command '{}', type 'help' for commands.", command)?,
+        }
+
+        Ok(true)
+    }
+
+    fn bad_argument(&self, words: &[&str], output: &mut impl Write) -> io::Result<bool> {
+        writeln!(output, "Bad or missing argument for '{}', type 'help' for commands.", words.join(" "))?;
+        Ok(true)
+    }
+
+    /// Execute one instruction, returning the reason to stop if there is one.
+    fn step(&mut self) -> Option<StopReason> {
+
+        match self.state.step() {
+            Ok(Outcome::Halt) => return Some(StopReason::Halted),
+            Ok(_) => {}
+            Err(error) => return Some(StopReason::Error(error)),
+        }
+
+        // Refresh every watched value, stopping on the first that changed.
+        //
+        let mut changed = None;
+
+        for (address, last) in self.watchpoints.iter_mut() {
+            let value = self.state.read(*address);
+
+            if value != *last {
+                let old = std::mem::replace(last, value);
+                let new = self.state.read(*address);
+                changed = changed.or(Some(StopReason::Watchpoint { address: *address, old, new }));
+            }
+        }
+
+        if changed.is_some() {
+            return changed;
+        }
+
+        let address = self.state.instruction_pointer();
+        if self.breakpoints.contains(&address) {
+            return Some(StopReason::Breakpoint(address));
+        }
+
+        None
+    }
+
+    /// Take the current value of every watched address, so restoring a
+    /// snapshot doesn't look like a change.
+    fn refresh_watchpoints(&mut self) {
+        for (address, last) in self.watchpoints.iter_mut() {
+            *last = self.state.read(*address);
+        }
+    }
+
+    fn report(&self, reason: StopReason, output: &mut impl Write) -> io::Result<()> {
+        match reason {
+            StopReason::Breakpoint(address) => writeln!(output, "Breakpoint at {:04}", address),
+            StopReason::Watchpoint { address, old, new } => {
+                writeln!(output, "Watchpoint [{}]: {} -> {}", address, old, new)
+            }
+            StopReason::Halted => writeln!(output, "Program halted after {} steps", self.state.steps()),
+            StopReason::Error(error) => writeln!(output, "Execution failed: {}", error),
+        }
+    }
+
+    fn show_location(&self, output: &mut impl Write) -> io::Result<()> {
+        let line = disassembler::decode_memory(self.state.memory(), self.state.instruction_pointer());
+        writeln!(output, "=> {}", line)
+    }
+
+    fn dump(&self, address: usize, radius: usize, hex: bool, output: &mut impl Write) -> io::Result<()> {
+
+        // Align rows so the same address always lands in the same column.
+        //
+        let start = address.saturating_sub(radius) / DUMP_ROW_LENGTH * DUMP_ROW_LENGTH;
+        let end = address.saturating_add(radius);
+
+        for row in (start..=end).step_by(DUMP_ROW_LENGTH) {
+            write!(output, "{:04}:", row)?;
+
+            for current in row..(row + DUMP_ROW_LENGTH).min(end + 1) {
+                let value = self.state.read(current);
+                let text = if hex { cell::to_hex(&value) } else { value.to_string() };
+                let marker = if current == address { '>' } else { ' ' };
+
+                write!(output, " {}{:>8}", marker, text)?;
+            }
+
+            writeln!(output)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Commands read from stdin, a fresh line at a time, so a program being
+/// debugged on the console can share stdin for its own input.
+pub fn stdin_commands() -> impl Iterator<Item = String> {
+    std::iter::from_fn(|| {
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    })
+}
+
+#[cfg(test)]
+fn debug_session(instructions: &[i64], commands: &[&str]) -> String {
+    let state = ProgramState::from_vec(cell::from_slice(instructions));
+    let mut debugger = Debugger::new(state);
+    let mut output = Vec::new();
+
+    debugger.run(commands.iter().map(|command| command.to_string()), &mut output).unwrap();
+
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_debugger_step_and_regs() {
+    let output = debug_session(&[1,9,10,3,2,3,11,0,99,30,40,50], &["step", "", "regs", "step"]);
+
+    assert!(output.contains("=> 0000  1,9,10,3            ADD [9], [10], [3]"));
+    assert!(output.contains("=> 0004  2,3,11,0            MUL [3], [11], [0]"));
+    assert!(output.contains("ip=8 rb=0 steps=2"));
+    assert!(output.contains("Program halted after 3 steps"));
+}
+
+#[test]
+fn test_debugger_breakpoints() {
+    let output = debug_session(&[1,9,10,3,2,3,11,0,99,30,40,50], &["break 8", "continue", "x 0 2", "continue"]);
+
+    assert!(output.contains("Breakpoint at 0008\n=> 0008  99"));
+    assert!(output.contains("0000: >    3500         9        10"));
+    assert!(output.contains("Program halted after 3 steps"));
+}
+
+#[test]
+fn test_debugger_watchpoints() {
+    // Count a cell down from three.
+    //
+    let program = [1001,9,-1,9,1005,9,0,99,0,3];
+    let output = debug_session(&program, &["watch 9", "continue", "continue", "unwatch 9", "continue"]);
+
+    assert!(output.contains("Watchpoint at [9] = 3"));
+    assert!(output.contains("Watchpoint [9]: 3 -> 2\n=> 0004"));
+    assert!(output.contains("Watchpoint [9]: 2 -> 1\n=> 0004"));
+    assert!(output.contains("Deleted watchpoint at [9]"));
+    assert!(output.contains("Program halted after 7 steps"));
+}
+
+#[test]
+fn test_debugger_dump_and_errors() {
+    let output = debug_session(&[104,-255,99], &["xh 1 1", "x", "frob", "step 2", "step", "quit", "step"]);
+
+    assert!(output.contains("0000:        68 >     -ff        63"));
+    assert!(output.contains("Bad or missing argument for 'x'"));
+    assert!(output.contains("Unknown command 'frob'"));
+    assert!(output.contains("Program halted after 2 steps"));
+
+    // Stepping the halt again stays halted, and nothing runs after quit.
+    //
+    assert_eq!(output.matches("Program halted").count(), 2);
+}
+
+#[test]
+fn test_debugger_reports_errors() {
+    let output = debug_session(&[1,0,0,0,42], &["continue"]);
+
+    assert!(output.contains("Execution failed: unknown opcode at position 4 (instruction 42)"));
+}
+
+#[test]
+fn test_debugger_save_and_load() {
+    let path = std::env::temp_dir().join(format!("intcode-debugger-{}.snapshot", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let save = format!("save {}", path);
+    let load = format!("load {}", path);
+    let output = debug_session(&[1,9,10,3,2,3,11,0,99,30,40,50], &["step", &save, "step", &load, "regs", "load /no/such/file"]);
+    std::fs::remove_file(path).unwrap();
+
+    assert!(output.contains(&format!("Saved snapshot to {}", path)));
+    assert!(output.contains("ip=4 rb=0 steps=1"));
+    assert!(output.contains("Failed to load /no/such/file"));
+}
+
+#[test]
+fn test_debugger_runs_backwards() {
+    // Count [13] down from three, then write 42 to [0] and halt.
+    //
+    let program = [1001,13,-1,13,1005,13,0,1102,6,7,0,99,0,3];
+    let output = debug_session(&program, &["continue", "x 0 0", "backto 0", "x 0 0", "backto 12", "back 2", "regs", "rewind", "back"]);
+
+    assert!(output.contains("0000: >      42"));
+    assert!(output.contains("Undid 2 steps to the last write to [0]\n=> 0007  1102,6,7,0"));
+    assert!(output.contains("0000: >    1001"));
+    assert!(output.contains("No write to [12] to go back to"));
+    assert!(output.contains("ip=0 rb=0 steps=4"));
+    assert!(output.contains("Undid 4 steps\n=> 0000"));
+    assert!(output.contains("Back at the start after undoing 0 steps"));
+}
+
+#[test]
+fn test_debugger_top_of_memory() {
+    let output = debug_session(&[99], &["x 18446744073709551615", "list 18446744073709551615 3", "x 18446744073709551600 0"]);
+
+    assert!(output.contains("Bad or missing argument for 'x 18446744073709551615'"));
+    assert!(output.contains("   18446744073709551615  0                   DATA 0\n(dbg) "));
+    assert!(output.contains("18446744073709551600: >       0"));
+}
@@ -0,0 +1,194 @@
+
+/*!
+Disassembler producing an annotated listing of an Intcode program image.
+
+The image is swept linearly from address 0. Each cell that decodes to a known
+opcode with all of its parameters inside the image is listed as an instruction,
+anything else is listed as a single `DATA` cell.
+
+```text
+0000  1002,4,3,4          MUL [4], 3, [4]
+0004  33                  DATA 33
+```
+
+Operands are shown by mode: `[12]` is position mode, `12` is immediate mode,
+and `[rb+12]` is relative to the relative base.
+*/
+
+use std::fmt;
+
+use crate::cell::Cell;
+use crate::memory::Memory;
+use crate::{lookup_opcode, Instruction, ParameterMode};
+
+/// A single decoded parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operand {
+    pub mode: ParameterMode,
+    pub value: Cell,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            ParameterMode::Position => write!(f, "[{}]", self.value),
+            ParameterMode::Immediate => write!(f, "{}", self.value),
+            ParameterMode::Relative => write!(f, "[rb{:+}]", self.value),
+        }
+    }
+}
+
+/// One line of the listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ListingLine {
+    pub address: usize,
+    pub cells: Vec<Cell>,
+
+    // The opcode and its mnemonic, or `None` for a cell that isn't an
+    // instruction.
+    //
+    pub opcode: Option<u32>,
+    pub mnemonic: Option<&'static str>,
+    pub operands: Vec<Operand>,
+}
+
+impl fmt::Display for ListingLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cells: Vec<String> = self.cells.iter().map(|cell| cell.to_string()).collect();
+
+        write!(f, "{:04}  {:<18}  {}", self.address, cells.join(","), self.instruction())
+    }
+}
+
+impl ListingLine {
+
+    /// The instruction and its operands, without the address and cells.
+    pub fn instruction(&self) -> String {
+        match self.mnemonic {
+            Some(mnemonic) if self.operands.is_empty() => mnemonic.to_string(),
+            Some(mnemonic) => {
+                let operands: Vec<String> = self.operands.iter().map(|operand| operand.to_string()).collect();
+                format!("{} {}", mnemonic, operands.join(", "))
+            }
+            None => format!("DATA {}", self.cells[0]),
+        }
+    }
+}
+
+/// Decode the instruction at `address`, if there is a complete one in the
+/// image.
+pub fn decode_at(image: &[Cell], address: usize) -> Option<ListingLine> {
+    let instruction = Instruction::decode(image.get(address)?).ok()?;
+    let op_code = lookup_opcode(instruction.opcode).ok()?;
+
+    let end = address + 1 + op_code.parameter_count();
+    if end > image.len() {
+        return None;
+    }
+
+    let operands = image[address + 1..end].iter()
+        .cloned()
+        .zip(instruction.modes.iter())
+        .map(|(value, mode)| Operand { mode: *mode, value })
+        .collect();
+
+    Some(ListingLine {
+        address,
+        cells: image[address..end].to_vec(),
+        opcode: Some(instruction.opcode),
+        mnemonic: Some(op_code.mnemonic()),
+        operands,
+    })
+}
+
+/// Decode the instruction at `address` in memory, or a `DATA` line
+/// if there isn't one there. Memory ends at the top of the address space,
+/// so an instruction that would run past it is data too.
+pub fn decode_memory(memory: &Memory, address: usize) -> ListingLine {
+    let window: Vec<Cell> = (address..=address.saturating_add(Instruction::MAX_PARAMETERS))
+        .map(|current| memory.read(current))
+        .collect();
+
+    let mut line = decode_at(&window, 0).unwrap_or_else(|| data_line(&window, 0));
+    line.address = address;
+    line
+}
+
+fn data_line(image: &[Cell], address: usize) -> ListingLine {
+    ListingLine {
+        address,
+        cells: image[address..=address].to_vec(),
+        opcode: None,
+        mnemonic: None,
+        operands: Vec::new(),
+    }
+}
+
+/// Disassemble the whole image.
+pub fn disassemble(image: &[Cell]) -> Vec<ListingLine> {
+    let mut lines = Vec::new();
+    let mut address = 0;
+
+    while address < image.len() {
+        let line = decode_at(image, address).unwrap_or_else(|| data_line(image, address));
+
+        address += line.cells.len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Disassemble the whole image into printable text, one line per entry.
+pub fn listing(image: &[Cell]) -> String {
+    disassemble(image).iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[test]
+fn test_disassemble_sample() {
+    let image = crate::cell::from_slice(&[1,9,10,3,2,3,11,0,99,30,40,50]);
+
+    assert_eq!(listing(&image), "\
+0000  1,9,10,3            ADD [9], [10], [3]
+0004  2,3,11,0            MUL [3], [11], [0]
+0008  99                  HLT
+0009  30                  DATA 30
+0010  40                  DATA 40
+0011  50                  DATA 50
+");
+}
+
+#[test]
+fn test_disassemble_modes() {
+    let image = crate::cell::from_slice(&[1002,4,3,4,109,-1,204,1,1105,1,0,3]);
+
+    let lines = disassemble(&image);
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[0].to_string(), "0000  1002,4,3,4          MUL [4], 3, [4]");
+    assert_eq!(lines[0].opcode, Some(2));
+    assert_eq!(lines[1].to_string(), "0004  109,-1              ARB -1");
+    assert_eq!(lines[2].to_string(), "0006  204,1               OUT [rb+1]");
+    assert_eq!(lines[3].to_string(), "0008  1105,1,0            JNZ 1, 0");
+
+    // An input missing its parameter at the end of the image is data.
+    //
+    assert_eq!(lines[4].opcode, None);
+    assert_eq!(lines[4].mnemonic, None);
+    assert_eq!(lines[4].to_string(), "0011  3                   DATA 3");
+
+    assert_eq!(decode_at(&image, 11), None);
+    assert_eq!(decode_at(&image, 12), None);
+    assert_eq!(decode_at(&image, usize::MAX), None);
+}
+
+#[test]
+fn test_decode_memory() {
+    let memory = Memory::from_vec(crate::cell::from_slice(&[1002,4,3,4,3]));
+
+    assert_eq!(decode_memory(&memory, 0).to_string(), "0000  1002,4,3,4          MUL [4], 3, [4]");
+
+    // Memory past the image reads as zero, so the trailing input is complete.
+    //
+    assert_eq!(decode_memory(&memory, 4).to_string(), "0004  3,0                 IN [0]");
+    assert_eq!(decode_memory(&memory, 100).to_string(), "0100  0                   DATA 0");
+}
@@ -0,0 +1,90 @@
+
+/*!
+The errors that stop an Intcode program, and where they happened.
+*/
+
+use std::error::Error;
+use std::fmt;
+
+use crate::cell::Cell;
+
+/// The kind of failure that stopped an Intcode program.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ErrorKind {
+
+    // The instruction doesn't hold an opcode the interpreter knows about.
+    //
+    UnknownOpcode,
+
+    // A parameter resolved to an address outside of memory.
+    //
+    BadAddress(Cell),
+
+    // A mode digit is unknown, or can't be used by the parameter it applies
+    // to, like an immediate mode parameter that is written to.
+    //
+    BadParameterMode { parameter: usize, mode: i64 },
+
+    // The program executed its full step limit without halting.
+    //
+    StepLimitExceeded(u64),
+
+    // With loop detection on, the machine came back to a state it had been
+    // in the given number of steps earlier, with no I/O in between, so it
+    // would repeat those steps forever.
+    //
+    InfiniteLoop { period: u64 },
+
+    // An arithmetic result doesn't fit in a memory cell.
+    //
+    Overflow,
+
+    // The input opcode ran with no input queued and no device to ask.
+    //
+    InputUnavailable,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownOpcode => write!(f, "unknown opcode"),
+            ErrorKind::BadAddress(address) => write!(f, "bad address {}", address),
+            ErrorKind::BadParameterMode { parameter, mode } => {
+                write!(f, "bad mode {} for parameter {}", mode, parameter)
+            }
+            ErrorKind::StepLimitExceeded(limit) => write!(f, "step limit of {} exceeded", limit),
+            ErrorKind::InfiniteLoop { period } => write!(f, "infinite loop repeating every {} steps", period),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::InputUnavailable => write!(f, "no input available"),
+        }
+    }
+}
+
+/// An error raised while executing an Intcode program, along with the
+/// instruction pointer and raw instruction value at the point of failure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecError {
+    pub kind: ErrorKind,
+    pub instruction_pointer: usize,
+    pub instruction: Cell,
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at position {} (instruction {})",
+            self.kind, self.instruction_pointer, self.instruction)
+    }
+}
+
+impl Error for ExecError {}
+
+#[test]
+fn test_exec_error_display() {
+    let error = ExecError {
+        kind: ErrorKind::BadParameterMode { parameter: 3, mode: 1 },
+        instruction_pointer: 4,
+        instruction: Cell::from(11101),
+    };
+
+    assert_eq!(error.to_string(), "bad mode 1 for parameter 3 at position 4 (instruction 11101)");
+}
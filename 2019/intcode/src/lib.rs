@@ -0,0 +1,1724 @@
+
+/*!
+An interpreter for the Intcode computer from Advent of Code 2019.
+
+Programs are loaded from their comma separated text with the `loader`, and
+run by a `ProgramState`, which holds the machine's memory and registers:
+
+```
+use intcode::cell::Cell;
+use intcode::{loader, ProgramState, RunStatus};
+
+// Add the input to 5, output the sum, then halt.
+let program = loader::parse("3,9,1001,9,5,9,4,9,99,0").unwrap();
+let mut state = ProgramState::from_vec(program);
+
+state.push_input(Cell::from(37));
+assert_eq!(state.run().unwrap(), RunStatus::Output(Cell::from(42)));
+assert_eq!(state.run().unwrap(), RunStatus::Halted);
+```
+
+The main entry points are:
+
+- Loading: `loader::parse`, `loader::load` and `loader::load_stdin` read a
+  program, and `ProgramState::from_vec` boots a machine with it.
+- Patching: `ProgramState::patch` writes a cell before the program runs, and
+  `patch_noun_and_verb` sets the two cells day 2 calls the noun and verb.
+- Running: `execute` runs until the program halts, `run` also hands control
+  back whenever it outputs a value or waits for input, and `step` executes a
+  single instruction.
+- Guards: `set_step_limit` and `set_loop_detection` turn a program that
+  never halts into an error instead of a hang.
+- I/O: `push_input` queues input values and `take_output` drains output.
+  Devices attached with `set_input_device` and `set_output_device` take over
+  once the queue is empty, or instead of it.
+- Extending: an `OpCodeRegistry` attached with `set_opcodes` adds opcodes
+  to, or replaces, the standard set.
+- Reversing: with `set_undo_log` on, `step_back`, `back_to_write` and
+  `rewind` undo the steps taken, back to where the log began.
+
+The rest of the crate builds on the machine: an assembler and disassembler,
+control-flow graphs, an interactive debugger, tracing, snapshots, symbolic
+execution, an ASCII console, and the amplifier chains and networks later
+days are made of.
+*/
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+
+pub mod amplifier;
+pub mod ascii;
+pub mod assembler;
+pub mod cell;
+pub mod control_flow;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod loader;
+pub mod memory;
+pub mod network;
+pub mod profiler;
+pub mod registry;
+pub mod snapshot;
+pub mod symbolic;
+pub mod tracer;
+pub mod undo;
+
+use cell::Cell;
+use error::{ErrorKind, ExecError};
+use memory::Memory;
+use profiler::Profile;
+use registry::OpCodeRegistry;
+use snapshot::Snapshot;
+use tracer::{TraceEvent, Tracer};
+use undo::{UndoEntry, UndoLog};
+
+
+/// How a program finished executing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitStatus {
+
+    // The program ran its halt opcode.
+    //
+    Halted,
+}
+
+/// Why a resumable run handed control back to the caller.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RunStatus {
+
+    // The input opcode is waiting for a value, queue one and run again.
+    //
+    NeedsInput,
+
+    // The program produced a value.
+    //
+    Output(Cell),
+
+    // The program ran its halt opcode.
+    //
+    Halted,
+}
+
+/// What executing a single instruction asks of the machine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+
+    // Continue executing.
+    //
+    Continue,
+
+    // Continue executing from the given address.
+    //
+    Jump(usize),
+
+    // Halt execution
+    //
+    Halt, 
+}
+
+/// How the parameter of an instruction should be interpreted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParameterMode {
+
+    // The parameter is the address of the value.
+    //
+    Position,
+
+    // The parameter is the value itself.
+    //
+    Immediate,
+
+    // The parameter is an address offset from the relative base.
+    //
+    Relative,
+}
+
+impl ParameterMode {
+
+    /// The mode a digit of an instruction's value stands for.
+    pub fn from_digit(digit: i64) -> Option<Self> {
+        match digit {
+            0 => Some(ParameterMode::Position),
+            1 => Some(ParameterMode::Immediate),
+            2 => Some(ParameterMode::Relative),
+            _ => None,
+        }
+    }
+}
+
+/// A decoded instruction value: the two digit opcode, followed by
+/// one mode digit per parameter read right-to-left from the hundreds place.
+///
+/// For example 1002 decodes to opcode 2 with modes [Position, Immediate, Position].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Instruction {
+    pub opcode: u32,
+    pub modes: [ParameterMode; Instruction::MAX_PARAMETERS],
+}
+
+impl Instruction {
+
+    pub const MAX_PARAMETERS: usize = 3;
+
+    /// Split an instruction value into its opcode and parameter modes.
+    pub fn decode(value: &Cell) -> Result<Self, ErrorKind> {
+        let value = match cell::to_i64(value) {
+            Some(value) if value >= 0 => value,
+            _ => return Err(ErrorKind::UnknownOpcode),
+        };
+
+        let mut modes = [ParameterMode::Position; Instruction::MAX_PARAMETERS];
+        let mut mode_digits = value / 100;
+
+        for (index, mode) in modes.iter_mut().enumerate() {
+            let digit = mode_digits % 10;
+
+            *mode = match ParameterMode::from_digit(digit) {
+                Some(mode) => mode,
+                None => return Err(ErrorKind::BadParameterMode { parameter: index + 1, mode: digit }),
+            };
+
+            mode_digits /= 10;
+        }
+
+        Ok(Self {
+            opcode: (value % 100) as u32,
+            modes,
+        })
+    }
+}
+
+#[test]
+fn test_instruction_decode() {
+    let instruction = Instruction::decode(&Cell::from(1002)).unwrap();
+    assert_eq!(instruction.opcode, 2);
+    assert_eq!(instruction.modes, [ParameterMode::Position, ParameterMode::Immediate, ParameterMode::Position]);
+
+    let instruction = Instruction::decode(&Cell::from(21101)).unwrap();
+    assert_eq!(instruction.opcode, 1);
+    assert_eq!(instruction.modes, [ParameterMode::Immediate, ParameterMode::Immediate, ParameterMode::Relative]);
+
+    let instruction = Instruction::decode(&Cell::from(99)).unwrap();
+    assert_eq!(instruction.opcode, 99);
+    assert_eq!(instruction.modes, [ParameterMode::Position; 3]);
+
+    assert_eq!(Instruction::decode(&Cell::from(301)), Err(ErrorKind::BadParameterMode { parameter: 1, mode: 3 }));
+    assert_eq!(Instruction::decode(&Cell::from(-1)), Err(ErrorKind::UnknownOpcode));
+}
+
+/// A source of values for the input opcode, consulted once the
+/// program's input queue has been drained.
+pub trait InputDevice {
+    fn read(&mut self) -> Option<Cell>;
+}
+
+/// Any closure producing values can drive the input opcode,
+/// including an iterator adapted with `move || iter.next()`.
+impl<F: FnMut() -> Option<Cell>> InputDevice for F {
+    fn read(&mut self) -> Option<Cell> {
+        self()
+    }
+}
+
+/// A destination for values produced by the output opcode.
+pub trait OutputDevice {
+    fn write(&mut self, value: Cell);
+}
+
+impl<F: FnMut(Cell)> OutputDevice for F {
+    fn write(&mut self, value: Cell) {
+        self(value)
+    }
+}
+
+/// Prompt for and read one value per line from stdin.
+pub struct ConsoleInput;
+impl InputDevice for ConsoleInput {
+    fn read(&mut self) -> Option<Cell> {
+        let stdin = io::stdin();
+
+        loop {
+            print!("input> ");
+            io::stdout().flush().ok()?;
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+
+            match line.trim().parse::<Cell>() {
+                Ok(value) => return Some(value),
+                Err(_) => println!("Not a valid value: {}", line.trim()),
+            }
+        }
+    }
+}
+
+/// Print each output value on its own line to stdout.
+pub struct ConsoleOutput;
+impl OutputDevice for ConsoleOutput {
+    fn write(&mut self, value: Cell) {
+        println!("{}", value);
+    }
+}
+
+/// An instruction the machine can execute, looked up by its opcode.
+///
+/// Implement this to add an opcode to an `OpCodeRegistry`.
+pub trait OpCode {
+
+    /// The number of parameters that follow the opcode in memory.
+    fn parameter_count(&self) -> usize;
+
+    /// The short name used for the opcode in listings.
+    fn mnemonic(&self) -> &'static str;
+
+    /// Execute the opcode against the machine, whose parameter modes have
+    /// already been set from the instruction.
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind>;
+}
+
+/// Implementation of a halt op code.
+#[derive(Default)]
+pub struct HaltOpCode;
+impl OpCode for HaltOpCode {
+    fn parameter_count(&self) -> usize {
+        0
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "HLT"
+    }
+
+    fn execute(&self, _state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+        Ok(Outcome::Halt)
+    }
+}
+
+/// Implementation of an addition op code.
+#[derive(Default)]
+pub struct AddOpCode;
+impl OpCode for AddOpCode {
+    fn parameter_count(&self) -> usize {
+        3
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "ADD"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let arg_one = state.get_arg(1)?;
+        let arg_two = state.get_arg(2)?;
+
+        match cell::checked_add(&arg_one, &arg_two) {
+            Some(value) => state.store_arg(3, value)?,
+            None => return Err(ErrorKind::Overflow),
+        }
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of a multiplication op code.
+#[derive(Default)]
+pub struct MultOpCode;
+impl OpCode for MultOpCode {
+    fn parameter_count(&self) -> usize {
+        3
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "MUL"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let arg_one = state.get_arg(1)?;
+        let arg_two = state.get_arg(2)?;
+
+        match cell::checked_mul(&arg_one, &arg_two) {
+            Some(value) => state.store_arg(3, value)?,
+            None => return Err(ErrorKind::Overflow),
+        }
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of an input op code.
+#[derive(Default)]
+pub struct InputOpCode;
+impl OpCode for InputOpCode {
+    fn parameter_count(&self) -> usize {
+        1
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "IN"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let value = match state.read_input() {
+            Some(value) => value,
+            None => return Err(ErrorKind::InputUnavailable),
+        };
+
+        state.store_arg(1, value)?;
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of an output op code.
+#[derive(Default)]
+pub struct OutputOpCode;
+impl OpCode for OutputOpCode {
+    fn parameter_count(&self) -> usize {
+        1
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "OUT"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let value = state.get_arg(1)?;
+
+        state.write_output(value);
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of a jump-if-true op code.
+#[derive(Default)]
+pub struct JumpIfTrueOpCode;
+impl OpCode for JumpIfTrueOpCode {
+    fn parameter_count(&self) -> usize {
+        2
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "JNZ"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        if state.get_arg(1)? != Cell::from(0) {
+            return Ok(Outcome::Jump(state.get_arg_address(2)?));
+        }
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of a jump-if-false op code.
+#[derive(Default)]
+pub struct JumpIfFalseOpCode;
+impl OpCode for JumpIfFalseOpCode {
+    fn parameter_count(&self) -> usize {
+        2
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "JZ"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        if state.get_arg(1)? == Cell::from(0) {
+            return Ok(Outcome::Jump(state.get_arg_address(2)?));
+        }
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of a less-than op code.
+#[derive(Default)]
+pub struct LessThanOpCode;
+impl OpCode for LessThanOpCode {
+    fn parameter_count(&self) -> usize {
+        3
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "LT"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let arg_one = state.get_arg(1)?;
+        let arg_two = state.get_arg(2)?;
+
+        state.store_arg(3, Cell::from(if arg_one < arg_two { 1 } else { 0 }))?;
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of an equals op code.
+#[derive(Default)]
+pub struct EqualsOpCode;
+impl OpCode for EqualsOpCode {
+    fn parameter_count(&self) -> usize {
+        3
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "EQ"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let arg_one = state.get_arg(1)?;
+        let arg_two = state.get_arg(2)?;
+
+        state.store_arg(3, Cell::from(if arg_one == arg_two { 1 } else { 0 }))?;
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// Implementation of a relative base adjustment op code.
+#[derive(Default)]
+pub struct AdjustRelativeBaseOpCode;
+impl OpCode for AdjustRelativeBaseOpCode {
+    fn parameter_count(&self) -> usize {
+        1
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        "ARB"
+    }
+
+    fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+
+        let adjustment = state.get_arg(1)?;
+
+        state.relative_base = match cell::checked_add(&state.relative_base, &adjustment) {
+            Some(relative_base) => relative_base,
+            None => return Err(ErrorKind::Overflow),
+        };
+
+        Ok(Outcome::Continue)
+    }
+}
+
+/// An Intcode machine: its memory and registers, queued I/O, and anything
+/// attached to it.
+pub struct ProgramState<'a> {
+    current_position: usize,
+    relative_base: Cell,
+    memory: Memory,
+
+    // The parameter modes of the instruction being executed, decoded once
+    // per step rather than for every parameter.
+    //
+    modes: [ParameterMode; Instruction::MAX_PARAMETERS],
+
+    // The number of instructions executed, and the optional limit on it.
+    //
+    steps: u64,
+    step_limit: Option<u64>,
+
+    // With loop detection on, the hash of every state seen since the last
+    // I/O, along with the step it was seen at.
+    //
+    seen_states: Option<HashMap<u64, u64>>,
+
+    // Values waiting to be consumed by the input opcode.
+    //
+    input: VecDeque<Cell>,
+
+    // Values produced by the output opcode when no output device is attached.
+    //
+    output: VecDeque<Cell>,
+
+    input_device: Option<Box<dyn InputDevice + 'a>>,
+    output_device: Option<Box<dyn OutputDevice + 'a>>,
+
+    // Sees every executed instruction, along with the values it read and
+    // the write it made. Parameters are read through `&self`, so the values
+    // read sit in a `RefCell`.
+    //
+    tracer: Option<Box<dyn Tracer + 'a>>,
+    last_reads: RefCell<Vec<Cell>>,
+    last_write: Option<(usize, Cell)>,
+
+    // Counts executions, reads and writes per address while profiling.
+    // Parameters are read through `&self`, so the counts sit in a `RefCell`.
+    //
+    profile: Option<RefCell<Profile>>,
+
+    // Records what every step changed, so it can be undone.
+    //
+    undo_log: Option<UndoLog>,
+
+    // The opcodes to dispatch through instead of `lookup_opcode`. They're
+    // shared so a step can hold one while it executes against the machine.
+    //
+    opcodes: Option<Rc<OpCodeRegistry<'a>>>,
+}
+
+impl<'a> ProgramState<'a> {
+
+    /// Boot a machine with `instruction_stream` loaded from address 0.
+    pub fn from_vec(instruction_stream: Vec<Cell>) -> Self {
+        Self { 
+            current_position: 0,
+            relative_base: Cell::from(0),
+            memory: Memory::from_vec(instruction_stream),
+            modes: [ParameterMode::Position; Instruction::MAX_PARAMETERS],
+            steps: 0,
+            step_limit: None,
+            seen_states: None,
+            input: VecDeque::new(),
+            output: VecDeque::new(),
+            input_device: None,
+            output_device: None,
+            tracer: None,
+            last_reads: RefCell::new(Vec::new()),
+            last_write: None,
+            profile: None,
+            undo_log: None,
+            opcodes: None,
+        }
+    }
+
+    /// Create a machine that resumes from a snapshot.
+    pub fn from_snapshot(snapshot: Snapshot) -> Self {
+        let mut state = Self::from_vec(Vec::new());
+        state.restore(snapshot);
+        state
+    }
+
+    /// Capture the machine's memory, registers and queued I/O.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            instruction_pointer: self.current_position,
+            relative_base: self.relative_base.to_owned(),
+            steps: self.steps,
+            memory: self.memory.clone(),
+            input: self.input.iter().cloned().collect(),
+            output: self.output.iter().cloned().collect(),
+        }
+    }
+
+    /// Replace the machine's state with a snapshot, keeping any attached
+    /// devices, tracer, profile, step limit and loop detection. The undo log
+    /// is kept on but emptied, since its steps led somewhere else.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.current_position = snapshot.instruction_pointer;
+        self.relative_base = snapshot.relative_base;
+        self.steps = snapshot.steps;
+        self.memory = snapshot.memory;
+        self.input = snapshot.input.into_iter().collect();
+        self.output = snapshot.output.into_iter().collect();
+        self.last_write = None;
+
+        if let Some(seen_states) = self.seen_states.as_mut() {
+            seen_states.clear();
+        }
+
+        if let Some(undo_log) = self.undo_log.as_mut() {
+            undo_log.clear();
+        }
+    }
+
+    /// Fail execution with `StepLimitExceeded` rather than executing
+    /// more than `limit` instructions.
+    pub fn set_step_limit(&mut self, limit: u64) {
+        self.step_limit = Some(limit);
+    }
+
+    /// Fail execution with `InfiniteLoop` as soon as the machine returns to a
+    /// state it was in before, rather than running forever.
+    ///
+    /// The instruction pointer, relative base and all of memory are hashed
+    /// before every step, which costs time in proportion to the size of
+    /// memory. Any input or output forgets the states seen, since the next
+    /// input could take the program elsewhere, and so does waiting for input.
+    pub fn set_loop_detection(&mut self, enabled: bool) {
+        self.seen_states = if enabled { Some(HashMap::new()) } else { None };
+    }
+
+    /// The number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Queue a value for the input opcode.
+    pub fn push_input(&mut self, value: Cell) {
+        self.input.push_back(value);
+    }
+
+    /// Attach a device to supply input once the input queue is empty.
+    pub fn set_input_device(&mut self, device: impl InputDevice + 'a) {
+        self.input_device = Some(Box::new(device));
+    }
+
+    /// Attach a device to receive output instead of the output queue.
+    pub fn set_output_device(&mut self, device: impl OutputDevice + 'a) {
+        self.output_device = Some(Box::new(device));
+    }
+
+    /// Attach a tracer to record every instruction executed from now on.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'a) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Start counting executions, reads and writes per address, from a
+    /// fresh profile.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(RefCell::new(Profile::new()));
+    }
+
+    /// Stop profiling, and return the profile gathered so far.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take().map(RefCell::into_inner)
+    }
+
+    /// Execute instructions with the opcodes in `registry`, rather than only
+    /// the standard ones.
+    pub fn set_opcodes(&mut self, registry: OpCodeRegistry<'a>) {
+        self.opcodes = Some(Rc::new(registry));
+    }
+
+    /// Record what every step from now on changes, so the steps can be undone
+    /// by `step_back`, `back_to_write` and `rewind`. Turning the log off
+    /// discards it.
+    pub fn set_undo_log(&mut self, enabled: bool) {
+        self.undo_log = if enabled { Some(UndoLog::new()) } else { None };
+    }
+
+    /// The steps that can be undone, if the undo log is on.
+    pub fn undo_log(&self) -> Option<&UndoLog> {
+        self.undo_log.as_ref()
+    }
+
+    /// Undo the last step recorded in the undo log, returning false if there
+    /// is none.
+    ///
+    /// Memory, the registers and the step count go back to how they were, and
+    /// any input the step consumed is pushed back onto the front of the input
+    /// queue, so running forward again replays it. Output is only taken back
+    /// while it's still in the output queue, and profiles and traces keep the
+    /// step.
+    pub fn step_back(&mut self) -> bool {
+        match self.undo_log.as_mut().and_then(UndoLog::pop) {
+            Some(entry) => {
+                self.revert(entry);
+                self.steps -= 1;
+                self.forget_states();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Step back to just before the last recorded instruction that wrote
+    /// `address`, leaving the instruction pointer on it, and return the
+    /// number of steps undone. If no recorded step wrote it the machine is
+    /// left alone.
+    pub fn back_to_write(&mut self, address: usize) -> Option<usize> {
+        let count = self.undo_log.as_ref()?.steps_since_write(address)?;
+
+        for _ in 0..count {
+            self.step_back();
+        }
+
+        Some(count)
+    }
+
+    /// Undo every recorded step, back to where the undo log began, and
+    /// return the number of steps undone.
+    pub fn rewind(&mut self) -> usize {
+        let mut count = 0;
+
+        while self.step_back() {
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Put back everything a step changed.
+    fn revert(&mut self, entry: UndoEntry) {
+        for (address, previous) in entry.writes.into_iter().rev() {
+            match previous {
+                Some(value) => self.memory.write(address, value),
+                None => self.memory.forget(address),
+            }
+        }
+
+        if let Some(value) = entry.input {
+            self.input.push_front(value);
+        }
+
+        if entry.queued_output {
+            self.output.pop_back();
+        }
+
+        self.current_position = entry.instruction_pointer;
+        self.relative_base = entry.relative_base;
+    }
+
+    /// Drain all output produced so far.
+    pub fn take_output(&mut self) -> Vec<Cell> {
+        self.output.drain(..).collect()
+    }
+
+    fn read_input(&mut self) -> Option<Cell> {
+
+        // Waiting for input counts as I/O too, or a caller running the
+        // program again before queueing any would look like a loop.
+        //
+        self.forget_states();
+
+        let value = match self.input.pop_front() {
+            Some(value) => value,
+            None => self.input_device.as_mut()?.read()?,
+        };
+
+        if let Some(entry) = self.undo_log.as_mut().and_then(UndoLog::current) {
+            entry.input = Some(value.to_owned());
+        }
+
+        Some(value)
+    }
+
+    fn write_output(&mut self, value: Cell) {
+        self.forget_states();
+
+        match self.output_device.as_mut() {
+            Some(device) => device.write(value),
+            None => {
+                self.output.push_back(value);
+
+                if let Some(entry) = self.undo_log.as_mut().and_then(UndoLog::current) {
+                    entry.queued_output = true;
+                }
+            }
+        }
+    }
+
+    /// Forget the states seen by loop detection, after I/O has happened.
+    fn forget_states(&mut self) {
+        if let Some(seen_states) = self.seen_states.as_mut() {
+            seen_states.clear();
+        }
+    }
+
+    /// Check whether the machine has been in its current state since the
+    /// last I/O, recording it if not and returning the state recorded.
+    ///
+    /// The I/O queues aren't part of the state. Only an input or output
+    /// instruction can touch them, and either one forgets every state seen.
+    fn check_for_loop(&mut self) -> Result<Option<u64>, ErrorKind> {
+        let seen_states = match self.seen_states.as_mut() {
+            Some(seen_states) => seen_states,
+            None => return Ok(None),
+        };
+
+        let mut hasher = DefaultHasher::new();
+        self.current_position.hash(&mut hasher);
+        self.relative_base.hash(&mut hasher);
+        self.memory.hash(&mut hasher);
+
+        let state = hasher.finish();
+        match seen_states.insert(state, self.steps) {
+            Some(step) => Err(ErrorKind::InfiniteLoop { period: self.steps - step }),
+            None => Ok(Some(state)),
+        }
+    }
+
+    /// The address of the next instruction to execute.
+    pub fn instruction_pointer(&self) -> usize {
+        self.current_position
+    }
+
+    /// The base address for relative mode parameters.
+    pub fn relative_base(&self) -> &Cell {
+        &self.relative_base
+    }
+
+    /// Read the value at `address`.
+    pub fn read(&self, address: usize) -> Cell {
+        self.memory.read(address)
+    }
+
+    /// The program's memory.
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Decode the instruction at the instruction pointer.
+    pub fn current_instruction(&self) -> Result<Instruction, ErrorKind> {
+        Instruction::decode(&self.memory.read(self.current_position))
+    }
+
+    /// Resolve the address the parameter at `index` (1 based) refers to.
+    fn parameter_address(&self, index: usize) -> Result<usize, ErrorKind> {
+        let parameter = self.memory.read(offset_address(self.current_position, index)?);
+
+        let address = match self.modes[index - 1] {
+            ParameterMode::Position => parameter,
+            ParameterMode::Relative => match cell::checked_add(&self.relative_base, &parameter) {
+                Some(address) => address,
+                None => return Err(ErrorKind::Overflow),
+            },
+            ParameterMode::Immediate => return Err(ErrorKind::BadParameterMode { parameter: index, mode: 1 }),
+        };
+
+        cell::to_usize(&address).ok_or(ErrorKind::BadAddress(address))
+    }
+
+    /// Read the value of the parameter at `index` (1 based), honoring its mode.
+    pub fn get_arg(&self, index: usize) -> Result<Cell, ErrorKind> {
+        let value = match self.modes[index - 1] {
+            ParameterMode::Immediate => self.memory.read(offset_address(self.current_position, index)?),
+            _ => {
+                let address = self.parameter_address(index)?;
+                if let Some(profile) = &self.profile {
+                    profile.borrow_mut().record_read(address);
+                }
+
+                self.memory.read(address)
+            }
+        };
+
+        if self.tracer.is_some() {
+            self.last_reads.borrow_mut().push(value.to_owned());
+        }
+
+        Ok(value)
+    }
+
+    /// Read the value of the parameter at `index` (1 based) as an address,
+    /// such as the target of a jump.
+    pub fn get_arg_address(&self, index: usize) -> Result<usize, ErrorKind> {
+        let value = self.get_arg(index)?;
+
+        cell::to_usize(&value).ok_or(ErrorKind::BadAddress(value))
+    }
+
+    /// Write `value` to the address named by the parameter at `index` (1 based).
+    pub fn store_arg(&mut self, index: usize, value: Cell) -> Result<(), ErrorKind> {
+        let position = self.parameter_address(index)?;
+
+        if let Some(entry) = self.undo_log.as_mut().and_then(UndoLog::current) {
+            entry.writes.push((position, self.memory.get(position).cloned()));
+        }
+
+        self.memory.write(position, value);
+
+        if self.tracer.is_some() {
+            self.last_write = Some((position, self.memory.read(position)));
+        }
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.get_mut().record_write(position);
+        }
+
+        Ok(())
+    }
+
+    /// Write `value` to `address`, such as to patch the program before it runs.
+    pub fn patch(&mut self, address: usize, value: Cell) {
+        self.memory.write(address, value);
+    }
+
+    /// Restore the gravity assist program to the "1202 program alarm" state it
+    /// had just before the last computer caught fire. To do this, before running
+    /// the program, replace position 1 with the value 12 and replace position 2 with the value 2. 
+    pub fn set_1202_alarm(&mut self) {
+        self.patch(1, Cell::from(12));
+        self.patch(2, Cell::from(2));
+    }
+
+    /// In this program, the value placed in address 1 is called the noun,
+    /// and the value placed in address 2 is called the verb. 
+    pub fn patch_noun_and_verb(&mut self, noun: Cell, verb: Cell) {
+        self.patch(1, noun);
+        self.patch(2, verb);
+    }
+
+    /// Run the program symbolically from its current state, treating the
+    /// cells at `patched` as variables, to express the memory it leaves
+    /// behind as polynomials in them.
+    pub fn execute_symbolic(&self, patched: &[usize]) -> Result<symbolic::SymbolicMemory, symbolic::SymbolicError> {
+        symbolic::execute(&self.memory, self.current_position, &self.relative_base, patched)
+    }
+
+    /// Run the program until it halts or fails.
+    pub fn execute(&mut self) -> Result<ExitStatus, ExecError> {
+
+        loop {
+            if self.step()? == Outcome::Halt {
+                return Ok(ExitStatus::Halted);
+            }
+        }
+    }
+
+    /// Run until the program needs input, produces output or halts.
+    ///
+    /// Each output is returned as soon as it's produced, unless an output
+    /// device is attached to take it. When input runs out the instruction
+    /// pointer is left on the input opcode, so pushing input and calling
+    /// `run` again picks up where it stopped.
+    pub fn run(&mut self) -> Result<RunStatus, ExecError> {
+
+        loop {
+            if let Some(value) = self.output.pop_front() {
+                return Ok(RunStatus::Output(value));
+            }
+
+            match self.step() {
+                Ok(Outcome::Halt) => return Ok(RunStatus::Halted),
+                Ok(_) => {}
+                Err(ExecError { kind: ErrorKind::InputUnavailable, .. }) => return Ok(RunStatus::NeedsInput),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Execute the single instruction at the instruction pointer.
+    pub fn step(&mut self) -> Result<Outcome, ExecError> {
+        self.try_step().map_err(|kind| ExecError {
+            kind,
+            instruction_pointer: self.current_position,
+            instruction: self.memory.read(self.current_position),
+        })
+    }
+
+    fn try_step(&mut self) -> Result<Outcome, ErrorKind> {
+
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(ErrorKind::StepLimitExceeded(limit));
+            }
+        }
+
+        // A step that fails never ran, so retrying it isn't a loop.
+        //
+        let state = self.check_for_loop()?;
+        let result = self.execute_instruction();
+
+        if let (Err(_), Some(state), Some(seen_states)) = (&result, state, self.seen_states.as_mut()) {
+            seen_states.remove(&state);
+        }
+
+        result
+    }
+
+    /// Execute the instruction at the instruction pointer, once the guards
+    /// have let it run.
+    fn execute_instruction(&mut self) -> Result<Outcome, ErrorKind> {
+        let instruction = self.current_instruction()?;
+        // Hold the registry apart from the machine, which the opcode is
+        // given to execute against.
+        //
+        let opcodes = self.opcodes.clone();
+        let op_action = match &opcodes {
+            Some(registry) => registry.lookup(instruction.opcode)?,
+            None => lookup_opcode(instruction.opcode)?,
+        };
+
+        self.modes = instruction.modes;
+
+        // Capture the instruction and its operands before it can overwrite
+        // them.
+        //
+        let address = self.current_position;
+        let (word, operands) = match self.tracer {
+            Some(_) => {
+                self.last_reads.get_mut().clear();
+                let operands = (1..=op_action.parameter_count())
+                    .filter_map(|index| address.checked_add(index))
+                    .map(|position| self.memory.read(position))
+                    .collect();
+                (self.memory.read(address), operands)
+            }
+            None => (Cell::from(0), Vec::new()),
+        };
+
+        // Disassemble the instruction for the profile the first time it runs
+        // at an address, before it can overwrite itself.
+        //
+        let listing = match &self.profile {
+            Some(profile) if profile.borrow().counts(address).executions == 0 => {
+                Some(disassembler::decode_memory(&self.memory, address))
+            }
+            _ => None,
+        };
+
+        // Open the undo entry the instruction records its changes in. A step
+        // that fails is put back straight away, as if it never started.
+        //
+        if let Some(undo_log) = self.undo_log.as_mut() {
+            undo_log.push(UndoEntry::new(address, self.relative_base.to_owned()));
+        }
+
+        // Work out where the instruction pointer goes next, which fails for an
+        // instruction at the very top of memory with nothing after it.
+        //
+        let next_position = |outcome| match outcome {
+
+            // Jump forward past the opcode and its parameters.
+            //
+            Outcome::Continue => offset_address(address, 1 + op_action.parameter_count()),
+
+            // The opcode has redirected the instruction pointer itself.
+            //
+            Outcome::Jump(target) => Ok(target),
+
+            // Leave the instruction pointer on the halt.
+            //
+            Outcome::Halt => Ok(address),
+        };
+
+        let (outcome, next) = match op_action.execute(self).and_then(|outcome| Ok((outcome, next_position(outcome)?))) {
+            Ok(step) => step,
+            Err(kind) => {
+                if let Some(entry) = self.undo_log.as_mut().and_then(UndoLog::pop) {
+                    self.revert(entry);
+                }
+
+                return Err(kind);
+            }
+        };
+
+        self.steps += 1;
+
+        if let Some(profile) = self.profile.as_mut() {
+            profile.get_mut().record_execution(address, listing);
+        }
+
+        if self.tracer.is_some() {
+            let event = TraceEvent {
+                step: self.steps,
+                instruction_pointer: address,
+                instruction: word,
+                opcode: instruction.opcode,
+                mnemonic: op_action.mnemonic(),
+                operands,
+                reads: self.last_reads.take(),
+                write: self.last_write.take(),
+            };
+
+            if let Some(tracer) = self.tracer.as_mut() {
+                tracer.trace(&event);
+            }
+        }
+
+        self.current_position = next;
+
+        Ok(outcome)
+    }
+}
+
+/// The address `offset` cells past `address`, which has to stay in memory.
+fn offset_address(address: usize, offset: usize) -> Result<usize, ErrorKind> {
+    address.checked_add(offset)
+        .ok_or_else(|| ErrorKind::BadAddress(cell::from_u128(address as u128 + offset as u128)))
+}
+
+/// Every opcode the interpreter supports.
+pub const OPCODES: [u32; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+
+/// The parameter (1 based) a standard opcode writes its result to, which
+/// can't be in immediate mode.
+pub(crate) fn written_parameter(opcode: u32) -> Option<usize> {
+    match opcode {
+        1 | 2 | 7 | 8 => Some(3),
+        3 => Some(1),
+        _ => None,
+    }
+}
+
+/// Look up the implementation of an opcode.
+///
+/// The opcodes are stateless, so each lives in a static and every step shares
+/// it, keeping dispatch free of allocation.
+/// Machines with an `OpCodeRegistry` attached look their opcodes up there
+/// instead.
+pub fn lookup_opcode(code: u32) -> Result<&'static dyn OpCode, ErrorKind> {
+    match code {
+        1 => Ok(&AddOpCode),
+        2 => Ok(&MultOpCode),
+        3 => Ok(&InputOpCode),
+        4 => Ok(&OutputOpCode),
+        5 => Ok(&JumpIfTrueOpCode),
+        6 => Ok(&JumpIfFalseOpCode),
+        7 => Ok(&LessThanOpCode),
+        8 => Ok(&EqualsOpCode),
+        9 => Ok(&AdjustRelativeBaseOpCode),
+        99 => Ok(&HaltOpCode),
+        _ => Err(ErrorKind::UnknownOpcode),
+    }
+}
+
+#[test]
+fn test_lookup_opcode() {
+    for code in OPCODES.iter() {
+        assert!(lookup_opcode(*code).is_ok(), "opcode {} is missing", code);
+    }
+
+    assert_eq!(lookup_opcode(42).err(), Some(ErrorKind::UnknownOpcode));
+    assert_eq!(lookup_opcode(2).map(|op_code| op_code.mnemonic()).ok(), Some("MUL"));
+}
+
+
+#[cfg(test)]
+fn assert_input_output(input: &[i64], expected: &[i64]) {
+
+    let mut program_state = ProgramState::from_vec(cell::from_slice(input));
+    assert_eq!(program_state.execute(), Ok(ExitStatus::Halted));
+
+    // The input would have been modified.
+    //
+    assert_eq!(cell::from_slice(expected), program_state.memory().image());
+}
+
+#[test]
+fn test_program_execution_add() {
+    // Execute a program that generates it's own terminates opcode.
+    //
+    let instructions = cell::from_slice(&[1,6,5,4,0,98,1]);
+    let mut program_state = ProgramState::from_vec(instructions);
+
+    program_state.execute().unwrap();
+}
+
+#[test]
+fn test_program_execution_mult() {
+    // Execute a program that generates it's own terminates opcode.
+    //
+    let instructions = cell::from_slice(&[2,6,5,4,0,99,1]);
+    let mut program_state = ProgramState::from_vec(instructions);
+
+    program_state.execute().unwrap();
+}
+
+#[test]
+fn test_program_execution_samples() {
+    {
+        let input = vec![1,0,0,0,99];
+        let expected = vec![2,0,0,0,99];
+        assert_input_output(&input, &expected);
+    }
+
+    {
+        let input = vec![2,3,0,3,99];
+        let expected = vec![2,3,0,6,99];
+        assert_input_output(&input, &expected);
+    }
+    
+    {
+        let input = vec![2,4,4,5,99,0];
+        let expected = vec![2,4,4,5,99,9801];
+        assert_input_output(&input, &expected);
+    }
+
+    {
+        let input = vec![1,1,1,4,99,5,6,0,99];
+        let expected = vec![30,1,1,4,2,5,6,0,99];
+        assert_input_output(&input, &expected);
+    }
+}
+
+#[test]
+fn test_program_execution_parameter_modes() {
+    // Multiply position 4 by the immediate 3, producing the halt opcode.
+    //
+    {
+        let input = vec![1002,4,3,4,33];
+        let expected = vec![1002,4,3,4,99];
+        assert_input_output(&input, &expected);
+    }
+
+    // Add two immediates, producing the halt opcode.
+    //
+    {
+        let input = vec![1101,49,50,4,0];
+        let expected = vec![1101,49,50,4,99];
+        assert_input_output(&input, &expected);
+    }
+}
+
+#[test]
+fn test_program_execution_input_output_queue() {
+    // Echo the input back out, then output the doubled input.
+    //
+    let instructions = cell::from_slice(&[3,0,4,0,1002,0,2,0,4,0,99]);
+    let mut program_state = ProgramState::from_vec(instructions);
+
+    program_state.push_input(Cell::from(21));
+    program_state.execute().unwrap();
+
+    assert_eq!(program_state.take_output(), cell::from_slice(&[21, 42]));
+    assert_eq!(program_state.take_output(), vec![]);
+}
+
+#[test]
+fn test_program_execution_input_output_devices() {
+    // Read two values and output their sum.
+    //
+    let instructions = cell::from_slice(&[3,11,3,12,1,11,12,13,4,13,99,0,0,0]);
+    let mut outputs = Vec::new();
+    {
+        let mut source = cell::from_slice(&[7, 35]).into_iter();
+        let mut program_state = ProgramState::from_vec(instructions);
+
+        program_state.set_input_device(move || source.next());
+        program_state.set_output_device(|value| outputs.push(value));
+        program_state.execute().unwrap();
+    }
+
+    assert_eq!(outputs, cell::from_slice(&[42]));
+}
+
+#[test]
+fn test_program_execution_resumable_run() {
+    // Double each input until a zero is read.
+    //
+    let instructions = cell::from_slice(&[3,100,1006,100,14,1002,100,2,100,4,100,1105,1,0,99]);
+    let mut program_state = ProgramState::from_vec(instructions);
+
+    assert_eq!(program_state.run().unwrap(), RunStatus::NeedsInput);
+    assert_eq!(program_state.run().unwrap(), RunStatus::NeedsInput);
+    assert_eq!(program_state.instruction_pointer(), 0);
+
+    program_state.push_input(Cell::from(5));
+    program_state.push_input(Cell::from(7));
+    assert_eq!(program_state.run().unwrap(), RunStatus::Output(Cell::from(10)));
+    assert_eq!(program_state.run().unwrap(), RunStatus::Output(Cell::from(14)));
+    assert_eq!(program_state.run().unwrap(), RunStatus::NeedsInput);
+
+    program_state.push_input(Cell::from(0));
+    assert_eq!(program_state.run().unwrap(), RunStatus::Halted);
+    assert_eq!(program_state.run().unwrap(), RunStatus::Halted);
+}
+
+#[test]
+fn test_program_execution_resumable_lockstep() {
+    // Two machines that each add one to their input, passing a value
+    // back and forth until it reaches ten.
+    //
+    let add_one = cell::from_slice(&[3,100,101,1,100,100,4,100,1105,1,0]);
+    let mut machines = [ProgramState::from_vec(add_one.clone()), ProgramState::from_vec(add_one)];
+
+    let mut value = Cell::from(0);
+    let mut turn = 0;
+
+    while value < Cell::from(10) {
+        machines[turn].push_input(value);
+        value = match machines[turn].run().unwrap() {
+            RunStatus::Output(value) => value,
+            status => panic!("Unexpected {:?}", status),
+        };
+
+        assert_eq!(machines[turn].run().unwrap(), RunStatus::NeedsInput);
+        turn = 1 - turn;
+    }
+
+    assert_eq!(value, Cell::from(10));
+    assert_eq!(machines[0].steps(), 20);
+    assert_eq!(machines[1].steps(), 20);
+}
+
+#[cfg(test)]
+fn run_with_input(instructions: &[i64], input: i64) -> Vec<i64> {
+
+    let mut program_state = ProgramState::from_vec(cell::from_slice(instructions));
+
+    program_state.push_input(Cell::from(input));
+    program_state.execute().unwrap();
+
+    program_state.take_output().iter().map(|value| cell::to_i64(value).unwrap()).collect()
+}
+
+#[test]
+fn test_program_execution_comparisons() {
+    // Is the input equal to 8, position then immediate mode.
+    //
+    let equals_position = [3,9,8,9,10,9,4,9,99,0,8];
+    assert_eq!(run_with_input(&equals_position, 8), vec![1]);
+    assert_eq!(run_with_input(&equals_position, 7), vec![0]);
+
+    let equals_immediate = [3,3,1108,0,8,3,4,3,99];
+    assert_eq!(run_with_input(&equals_immediate, 8), vec![1]);
+    assert_eq!(run_with_input(&equals_immediate, 9), vec![0]);
+
+    // Is the input less than 8, position then immediate mode.
+    //
+    let less_than_position = [3,9,7,9,10,9,4,9,99,0,8];
+    assert_eq!(run_with_input(&less_than_position, 5), vec![1]);
+    assert_eq!(run_with_input(&less_than_position, 8), vec![0]);
+
+    let less_than_immediate = [3,3,1107,0,8,3,4,3,99];
+    assert_eq!(run_with_input(&less_than_immediate, 5), vec![1]);
+    assert_eq!(run_with_input(&less_than_immediate, 10), vec![0]);
+}
+
+#[test]
+fn test_program_execution_jumps() {
+    // Output 0 if the input was zero or 1 if it was non-zero.
+    //
+    let jump_position = [3,12,6,12,15,1,13,14,13,4,13,99,0,0,1,9];
+    assert_eq!(run_with_input(&jump_position, 0), vec![0]);
+    assert_eq!(run_with_input(&jump_position, 3), vec![1]);
+
+    let jump_immediate = [3,3,1105,0,9,1101,0,0,12,4,12,99,1];
+    assert_eq!(run_with_input(&jump_immediate, 0), vec![0]);
+    assert_eq!(run_with_input(&jump_immediate, 3), vec![1]);
+
+    // Output 999 below 8, 1000 equal to 8, and 1001 above 8.
+    //
+    let compare_to_eight = [
+        3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,
+        1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,
+        999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
+    assert_eq!(run_with_input(&compare_to_eight, 7), vec![999]);
+    assert_eq!(run_with_input(&compare_to_eight, 8), vec![1000]);
+    assert_eq!(run_with_input(&compare_to_eight, 9), vec![1001]);
+}
+
+#[test]
+fn test_program_execution_relative_base() {
+    // Shift the relative base past the end of the program, read the
+    // input into that address, then write it back out.
+    //
+    let relative_echo = [109,10,203,0,204,0,99];
+    assert_eq!(run_with_input(&relative_echo, 42), vec![42]);
+
+    // Adjustments accumulate.
+    //
+    let relative_accumulate = [109,5,109,5,203,0,4,10,99];
+    assert_eq!(run_with_input(&relative_accumulate, 7), vec![7]);
+}
+
+#[test]
+fn test_program_execution_unbounded_memory() {
+    // Untouched memory reads as zero.
+    //
+    let read_untouched = [4,1000,99];
+    assert_eq!(run_with_input(&read_untouched, 0), vec![0]);
+
+    // Write far beyond the program, then read it back.
+    //
+    let write_far = [1101,40,2,100000,4,100000,99];
+    assert_eq!(run_with_input(&write_far, 0), vec![42]);
+}
+
+#[test]
+fn test_program_execution_signed_values() {
+    // Negative literals and placeholders from the day 5 and day 9 samples.
+    //
+    let equals_eight = [3,9,8,9,10,9,4,9,99,-1,8];
+    assert_eq!(run_with_input(&equals_eight, 8), vec![1]);
+    assert_eq!(run_with_input(&equals_eight, -8), vec![0]);
+
+    let add_negative = [1101,100,-1,4,0];
+    assert_input_output(&add_negative, &[1101,100,-1,4,99]);
+
+    let quine = [109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+    assert_eq!(run_with_input(&quine, 0), quine.to_vec());
+}
+
+#[test]
+fn test_program_execution_large_values() {
+    // Outputs a 16 digit number.
+    //
+    let sixteen_digits = [1102,34915192,34915192,7,4,7,99,0];
+    assert_eq!(run_with_input(&sixteen_digits, 0), vec![1219070632396864]);
+
+    let large_literal = [104,1125899906842624,99];
+    assert_eq!(run_with_input(&large_literal, 0), vec![1125899906842624]);
+}
+
+#[test]
+#[cfg(not(feature = "bigint"))]
+fn test_program_execution_mult_overflow() {
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[1102,i64::MAX,2,0,99]));
+
+    assert_eq!(program_state.execute().unwrap_err().kind, ErrorKind::Overflow);
+}
+
+#[test]
+#[cfg(feature = "bigint")]
+fn test_program_execution_mult_bigint() {
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[1102,i64::MAX,2,7,4,7,99,0]));
+    program_state.execute().unwrap();
+
+    assert_eq!(program_state.take_output(), vec![Cell::from(i64::MAX) * 2]);
+}
+
+#[test]
+#[cfg(feature = "bigint")]
+fn test_program_execution_top_of_memory_bigint() {
+    let top = Cell::from(usize::MAX);
+    let past_top = cell::from_u128(usize::MAX as u128 + 1);
+
+    // Write a jump to the last address and take it, leaving no room for the
+    // jump's own parameters.
+    //
+    let mut instructions = cell::from_slice(&[1101,1104,1,0,1105,1,0,99]);
+    instructions[3] = top.clone();
+    instructions[6] = top.clone();
+
+    let mut program_state = ProgramState::from_vec(instructions);
+    let error = program_state.execute().unwrap_err();
+    assert_eq!(error.kind, ErrorKind::BadAddress(past_top.clone()));
+    assert_eq!(error.instruction_pointer, usize::MAX);
+
+    // An instruction whose parameters end on the last address has nowhere to
+    // continue to.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[1105,1,0]));
+    program_state.patch(2, Cell::from(usize::MAX - 3));
+    program_state.patch(usize::MAX - 3, Cell::from(1101));
+
+    let error = program_state.execute().unwrap_err();
+    assert_eq!(error.kind, ErrorKind::BadAddress(past_top));
+    assert_eq!(error.instruction_pointer, usize::MAX - 3);
+}
+
+#[cfg(test)]
+fn execute_error(instructions: &[i64]) -> ExecError {
+    let mut program_state = ProgramState::from_vec(cell::from_slice(instructions));
+
+    program_state.execute().unwrap_err()
+}
+
+#[test]
+fn test_program_execution_errors() {
+    // An unknown opcode after a valid instruction.
+    //
+    let error = execute_error(&[1,0,0,0,42]);
+    assert_eq!(error.kind, ErrorKind::UnknownOpcode);
+    assert_eq!(error.instruction_pointer, 4);
+    assert_eq!(error.instruction, Cell::from(42));
+
+    // A negative position.
+    //
+    let error = execute_error(&[1,-1,0,0,99]);
+    assert_eq!(error.kind, ErrorKind::BadAddress(Cell::from(-1)));
+    assert_eq!(error.instruction_pointer, 0);
+
+    // A relative base that points below zero.
+    //
+    let error = execute_error(&[109,-5,204,0,99]);
+    assert_eq!(error.kind, ErrorKind::BadAddress(Cell::from(-5)));
+    assert_eq!(error.instruction_pointer, 2);
+
+    // An unknown mode, and an immediate mode write.
+    //
+    let error = execute_error(&[301,0,0,0,99]);
+    assert_eq!(error.kind, ErrorKind::BadParameterMode { parameter: 1, mode: 3 });
+
+    let error = execute_error(&[11101,1,1,0,99]);
+    assert_eq!(error.kind, ErrorKind::BadParameterMode { parameter: 3, mode: 1 });
+    assert_eq!(error.instruction, Cell::from(11101));
+
+    // Input with nothing to read.
+    //
+    let error = execute_error(&[3,0,99]);
+    assert_eq!(error.kind, ErrorKind::InputUnavailable);
+}
+
+#[test]
+fn test_program_execution_step_limit() {
+    // Jump back to the start forever.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[1105,1,0]));
+    program_state.set_step_limit(100);
+
+    let error = program_state.execute().unwrap_err();
+    assert_eq!(error.kind, ErrorKind::StepLimitExceeded(100));
+    assert_eq!(error.instruction_pointer, 0);
+    assert_eq!(program_state.steps(), 100);
+}
+
+#[test]
+fn test_program_execution_loop_detection() {
+    let looping = |instructions: &[i64]| {
+        let mut program_state = ProgramState::from_vec(cell::from_slice(instructions));
+        program_state.set_step_limit(1000);
+        program_state.set_loop_detection(true);
+        program_state.execute().map_err(|error| error.kind)
+    };
+
+    // Jump back to the start forever.
+    //
+    assert_eq!(looping(&[1105,1,0]), Err(ErrorKind::InfiniteLoop { period: 1 }));
+
+    // Flip [9] between 0 and 1, so the state repeats every other pass.
+    //
+    assert_eq!(looping(&[1007,9,1,9,1105,1,0,99,0,0]), Err(ErrorKind::InfiniteLoop { period: 4 }));
+
+    // Count up forever, never repeating a state.
+    //
+    assert_eq!(looping(&[1001,5,1,5,1105,1,0]), Err(ErrorKind::StepLimitExceeded(1000)));
+
+    // Output on every pass, so the identical states don't count.
+    //
+    assert_eq!(looping(&[104,7,1105,1,0]), Err(ErrorKind::StepLimitExceeded(1000)));
+
+    // Output once, then spin with it still queued.
+    //
+    assert_eq!(looping(&[104,7,1105,1,2]), Err(ErrorKind::InfiniteLoop { period: 1 }));
+
+    // Read one input, then spin with the other still queued.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[3,10,1105,1,2]));
+    program_state.set_step_limit(1000);
+    program_state.set_loop_detection(true);
+    program_state.push_input(Cell::from(1));
+    program_state.push_input(Cell::from(2));
+    assert_eq!(program_state.execute().map_err(|error| error.kind), Err(ErrorKind::InfiniteLoop { period: 1 }));
+
+    // Retrying a step that failed keeps reporting why it failed.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[1,-1,0,0,99]));
+    program_state.set_loop_detection(true);
+    assert_eq!(program_state.step().map_err(|error| error.kind), Err(ErrorKind::BadAddress(Cell::from(-1))));
+    assert_eq!(program_state.step().map_err(|error| error.kind), Err(ErrorKind::BadAddress(Cell::from(-1))));
+
+    // Waiting for input isn't a loop, however often the program is run.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[3,5,1105,1,0]));
+    program_state.set_loop_detection(true);
+    assert_eq!(program_state.run().unwrap(), RunStatus::NeedsInput);
+    assert_eq!(program_state.run().unwrap(), RunStatus::NeedsInput);
+    program_state.push_input(Cell::from(1));
+    assert_eq!(program_state.run().unwrap(), RunStatus::NeedsInput);
+}
+
+#[test]
+fn test_program_execution_undo() {
+    // Count [13] down from three, then write 42 to [0] and halt.
+    //
+    let instructions = cell::from_slice(&[1001,13,-1,13,1005,13,0,1102,6,7,0,99,0,3]);
+    let mut program_state = ProgramState::from_vec(instructions);
+    program_state.set_undo_log(true);
+
+    let start = program_state.snapshot();
+    assert_eq!(program_state.execute(), Ok(ExitStatus::Halted));
+    assert_eq!(program_state.read(0), Cell::from(42));
+    assert_eq!(program_state.undo_log().map(UndoLog::len), Some(8));
+
+    // Walk back to the multiply that wrote [0], then to the last decrement.
+    //
+    assert_eq!(program_state.back_to_write(0), Some(2));
+    assert_eq!(program_state.instruction_pointer(), 7);
+    assert_eq!(program_state.read(0), Cell::from(1001));
+    assert_eq!(program_state.steps(), 6);
+
+    assert_eq!(program_state.back_to_write(13), Some(2));
+    assert_eq!(program_state.instruction_pointer(), 0);
+    assert_eq!(program_state.read(13), Cell::from(1));
+
+    // Nothing wrote [12], so the machine stays put.
+    //
+    assert_eq!(program_state.back_to_write(12), None);
+    assert_eq!(program_state.steps(), 4);
+
+    assert_eq!(program_state.rewind(), 4);
+    assert!(!program_state.step_back());
+    assert_eq!(program_state.snapshot(), start);
+
+    // Running forward again gets the same answer.
+    //
+    assert_eq!(program_state.execute(), Ok(ExitStatus::Halted));
+    assert_eq!(program_state.read(0), Cell::from(42));
+}
+
+#[test]
+fn test_program_execution_undo_io() {
+    // Read into [100] past the image, move the relative base, echo it back.
+    //
+    let instructions = cell::from_slice(&[3,100,109,5,204,95,99]);
+    let mut program_state = ProgramState::from_vec(instructions);
+    program_state.set_undo_log(true);
+    program_state.push_input(Cell::from(7));
+
+    let start = program_state.snapshot();
+    assert_eq!(program_state.run().unwrap(), RunStatus::Output(Cell::from(7)));
+
+    // The input goes back on the queue, but the output has been taken.
+    //
+    assert_eq!(program_state.rewind(), 3);
+    assert_eq!(program_state.snapshot(), start);
+    assert_eq!(program_state.relative_base(), &Cell::from(0));
+
+    // Output still in the queue is taken back.
+    //
+    assert_eq!(program_state.execute(), Ok(ExitStatus::Halted));
+    assert!(program_state.step_back());
+    assert!(program_state.step_back());
+    assert_eq!(program_state.take_output(), Vec::<Cell>::new());
+    assert_eq!(program_state.instruction_pointer(), 4);
+
+    // A step that fails is undone, giving back the input it consumed.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[3,-1,99]));
+    program_state.set_undo_log(true);
+    program_state.push_input(Cell::from(5));
+
+    let start = program_state.snapshot();
+    assert_eq!(program_state.step().map_err(|error| error.kind), Err(ErrorKind::BadAddress(Cell::from(-1))));
+    assert_eq!(program_state.snapshot(), start);
+    assert_eq!(program_state.undo_log().map(UndoLog::is_empty), Some(true));
+}
+
+#[test]
+fn test_program_execution_registered_opcodes() {
+    use std::cell::RefCell;
+
+    // Print a value to a log, as a debugging extension might.
+    //
+    struct DebugPrintOpCode<'a>(&'a RefCell<Vec<Cell>>);
+    impl OpCode for DebugPrintOpCode<'_> {
+        fn parameter_count(&self) -> usize {
+            1
+        }
+
+        fn mnemonic(&self) -> &'static str {
+            "DBG"
+        }
+
+        fn execute(&self, state: &mut ProgramState) -> Result<Outcome, ErrorKind> {
+            self.0.borrow_mut().push(state.get_arg(1)?);
+            Ok(Outcome::Continue)
+        }
+    }
+
+    let log = RefCell::new(Vec::new());
+    let mut opcodes = OpCodeRegistry::standard();
+    opcodes.register(50, DebugPrintOpCode(&log)).unwrap();
+
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[1150,7,1101,1,2,9,50,9,99,0]));
+    program_state.set_opcodes(opcodes);
+    assert_eq!(program_state.execute(), Ok(ExitStatus::Halted));
+    assert_eq!(*log.borrow(), cell::from_slice(&[7,3]));
+
+    // Anything unregistered is still unknown, including standard opcodes
+    // the registry leaves out.
+    //
+    let mut program_state = ProgramState::from_vec(cell::from_slice(&[104,1,99]));
+    program_state.set_opcodes(OpCodeRegistry::new());
+    let error = program_state.step().unwrap_err();
+    assert_eq!(error.kind, ErrorKind::UnknownOpcode);
+    assert_eq!(error.to_string(), "unknown opcode at position 0 (instruction 104)");
+}
@@ -0,0 +1,188 @@
+
+/*!
+Loading Intcode programs from text.
+
+A program is a list of signed integers separated by commas. Whitespace is
+allowed anywhere between values, so a program can be split over as many
+lines as is convenient, and a single trailing comma is ignored. Everything
+from a `#` to the end of its line is a comment:
+
+```text
+# Add the values at 9 and 10, then halt.
+1,9,10,3,
+99,
+30, 40, -50,
+```
+
+Values on one line may also be separated by whitespace alone. Two commas
+with no value between them are an error rather than an implied zero.
+*/
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::cell::Cell;
+
+/// Why a program couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+
+    // The program's text couldn't be read.
+    //
+    Io(io::Error),
+
+    // A token isn't a valid value. Lines and columns are 1 based, and
+    // columns count characters rather than bytes.
+    //
+    BadToken { line: usize, column: usize, token: String },
+
+    // A comma, at the line and column given, doesn't follow a value.
+    //
+    MissingValue { line: usize, column: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "{}", error),
+            LoadError::BadToken { line, column, token } => {
+                write!(f, "line {}, column {}: invalid value '{}'", line, column, token)
+            }
+            LoadError::MissingValue { line, column } => {
+                write!(f, "line {}, column {}: missing value before ','", line, column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+/// Parse a program from its text.
+pub fn parse(text: &str) -> Result<Vec<Cell>, LoadError> {
+    let mut values = Vec::new();
+
+    // Whether the last thing seen was a value, so a comma may follow it.
+    //
+    let mut after_value = false;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let code = line.split('#').next().unwrap_or_default();
+        let mut chars = code.char_indices().zip(1..).peekable();
+
+        while let Some(((start, c), column)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+
+            if c == ',' {
+                if !after_value {
+                    return Err(LoadError::MissingValue { line: line_number, column });
+                }
+
+                after_value = false;
+                continue;
+            }
+
+            let mut end = start + c.len_utf8();
+            while let Some(&((next, c), _)) = chars.peek() {
+                if c.is_whitespace() || c == ',' {
+                    break;
+                }
+
+                end = next + c.len_utf8();
+                chars.next();
+            }
+
+            let token = &code[start..end];
+            match token.parse::<Cell>() {
+                Ok(value) => values.push(value),
+                Err(_) => {
+                    return Err(LoadError::BadToken { line: line_number, column, token: token.to_string() });
+                }
+            }
+
+            after_value = true;
+        }
+    }
+
+    Ok(values)
+}
+
+/// Read a program from `reader` until it ends.
+pub fn read(mut reader: impl Read) -> Result<Vec<Cell>, LoadError> {
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+
+    parse(&text)
+}
+
+/// Load a program from the file at `path`.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Cell>, LoadError> {
+    parse(&fs::read_to_string(path)?)
+}
+
+/// Load a program from standard input.
+pub fn load_stdin() -> Result<Vec<Cell>, LoadError> {
+    read(io::stdin().lock())
+}
+
+/// Load a program named on the command line, from standard input when the
+/// argument is `-` and from the file it names otherwise.
+pub fn load_arg(arg: &str) -> Result<Vec<Cell>, LoadError> {
+    match arg {
+        "-" => load_stdin(),
+        _ => load(arg),
+    }
+}
+
+#[test]
+fn test_parse_layouts() {
+    use crate::cell::from_slice;
+
+    assert_eq!(parse("1,9,10,3,2,3,11,0,99,30,40,50\n").unwrap(), from_slice(&[1,9,10,3,2,3,11,0,99,30,40,50]));
+    assert_eq!(parse(" 1, 0 ,0,3 ,\r\n\t99 ,").unwrap(), from_slice(&[1,0,0,3,99]));
+    assert_eq!(parse("# Header\n\n1,-2, +3 # Trailing\n4 5\n# 6,7\n").unwrap(), from_slice(&[1,-2,3,4,5]));
+    assert_eq!(parse("").unwrap(), from_slice(&[]));
+    assert_eq!(read("104,-1,99".as_bytes()).unwrap(), from_slice(&[104,-1,99]));
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |text| match parse(text) {
+        Err(LoadError::BadToken { line, column, token }) => Some((line, column, token)),
+        _ => None,
+    };
+
+    assert_eq!(error("1,2,x3"), Some((1, 5, "x3".to_string())));
+    assert_eq!(error("1,2,\n  99,\n é,4"), Some((3, 2, "é".to_string())));
+    assert_eq!(error("1,2;3"), Some((1, 3, "2;3".to_string())));
+    assert_eq!(error("1 - 2"), Some((1, 3, "-".to_string())));
+
+    assert!(matches!(parse("1,,2"), Err(LoadError::MissingValue { line: 1, column: 3 })));
+    assert_eq!(parse("1\n  ,2").unwrap(), crate::cell::from_slice(&[1,2]));
+    assert!(matches!(parse(",1"), Err(LoadError::MissingValue { line: 1, column: 1 })));
+
+    assert_eq!(parse("1,\n 2 x").unwrap_err().to_string(), "line 2, column 4: invalid value 'x'");
+}
+
+#[test]
+fn test_load_missing_file() {
+    assert!(matches!(load("no-such-program.txt"), Err(LoadError::Io(_))));
+    assert!(matches!(load_arg("no-such-program.txt"), Err(LoadError::Io(_))));
+}
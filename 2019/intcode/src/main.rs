@@ -0,0 +1,324 @@
+
+/*!
+Command line tools for Intcode programs, and drivers for the 2019 days that
+run them.
+
+Every command takes a program file, or `-` to read the program from stdin:
+
+```text
+intcode run <file> [inputs...]
+intcode resume <snapshot> [inputs...]
+intcode debug <file> [inputs...]
+intcode profile <file> table|csv [inputs...]
+intcode trace <file> <trace-file>|- [noun verb]
+intcode disasm <file>
+intcode asm <source>
+intcode cfg <file> [entry]
+intcode amp <file> <phases> [feedback]
+intcode ascii <file> [transcript]
+intcode network <file> [size]
+```
+
+`amp` is day 7's amplifier chain, `ascii` runs the ASCII programs of days
+17, 21 and 25, and `network` is day 23's cluster.
+
+Failures are reported on stderr with an exit status of 1, and unknown
+commands print the usage with an exit status of 2.
+*/
+
+use std::env;
+use std::io::{self, BufRead};
+use std::process;
+
+use intcode::cell::Cell;
+use intcode::snapshot::Snapshot;
+use intcode::tracer::{self, Tracer};
+use intcode::{amplifier, ascii, assembler, control_flow, debugger, disassembler, loader, network};
+use intcode::{ConsoleInput, ConsoleOutput, ProgramState};
+
+const USAGE: &str = "\
+usage: intcode <command> <file> [arguments...]
+
+  run <file> [inputs...]                Run a program on the console.
+  resume <snapshot> [inputs...]         Resume a program saved by the debugger.
+  debug <file> [inputs...]              Step through a program in the debugger.
+  profile <file> table|csv [inputs...]  Run a program and report where it spent its time.
+  trace <file> <trace-file>|- [noun verb]
+                                        Write a JSON Lines trace of every instruction.
+  disasm <file>                         Print an annotated listing.
+  asm <source>                          Assemble a source file.
+  cfg <file> [entry]                    Print the control-flow graph as Graphviz DOT.
+  amp <file> <phases> [feedback]        Find the best amplifier phase settings (day 7).
+  ascii <file> [transcript]             Talk to an ASCII program (days 17, 21 and 25).
+  network <file> [size]                 Run a network of machines (day 23).
+
+A <file> of - reads the program from stdin.
+";
+
+/// Load the program named by `filename`, exiting with the reason if it
+/// can't be loaded.
+fn load_or_exit(filename: &str) -> Vec<Cell> {
+    loader::load_arg(filename).unwrap_or_else(|error| {
+        eprintln!("Failed to load {}: {}", filename, error);
+        process::exit(1);
+    })
+}
+
+/// Run a program with its input and output wired to the console.
+/// Any `inputs` given are consumed before prompting for more.
+fn run_interactive(filename: &str, inputs: &[String]) -> Result<(), String> {
+    run_on_console(&mut ProgramState::from_vec(load_or_exit(filename)), inputs)
+}
+
+/// Resume a program saved with the debugger's `save` command.
+fn resume(filename: &str, inputs: &[String]) -> Result<(), String> {
+    let snapshot = Snapshot::load(filename).map_err(|error| format!("Failed to load {}: {}", filename, error))?;
+    run_on_console(&mut ProgramState::from_snapshot(snapshot), inputs)
+}
+
+fn run_on_console(state: &mut ProgramState, inputs: &[String]) -> Result<(), String> {
+    push_inputs(state, inputs)?;
+
+    state.set_input_device(ConsoleInput);
+    state.set_output_device(ConsoleOutput);
+
+    state.execute().map_err(|error| format!("Execution failed: {}", error))?;
+    Ok(())
+}
+
+/// Queue up the `inputs` given on the command line.
+fn push_inputs(state: &mut ProgramState, inputs: &[String]) -> Result<(), String> {
+    for input in inputs {
+        let value = input.parse::<Cell>().map_err(|_| format!("Not a valid input value: {}", input))?;
+        state.push_input(value);
+    }
+
+    Ok(())
+}
+
+/// Run a program on the console while profiling it, then print the hottest
+/// addresses as a table, or every address as CSV.
+///
+/// A program that fails is still reported on, up to where it failed.
+fn profile(filename: &str, format: &str, inputs: &[String]) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let mut state = ProgramState::from_vec(instructions);
+    state.enable_profiling();
+    let result = run_on_console(&mut state, inputs);
+
+    if let Some(profile) = state.take_profile() {
+        match format {
+            "csv" => print!("{}", profile.to_csv()),
+            _ => print!("{}", profile.table(HOT_SPOTS)),
+        }
+    }
+
+    result
+}
+
+/// Run a program that talks in ASCII, with its input typed at the terminal,
+/// or played back from the lines of `transcript` when one is given.
+fn run_ascii(filename: &str, transcript: Option<&String>) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let mut state = ProgramState::from_vec(instructions);
+    let mut stdout = io::stdout();
+
+    let result = match transcript {
+        Some(transcript) => match std::fs::read_to_string(transcript) {
+            Ok(text) => ascii::play(&mut state, ascii::transcript_lines(&text), true, &mut stdout),
+            Err(error) => return Err(format!("Failed to read {}: {}", transcript, error)),
+        },
+        None => {
+            let lines = io::stdin().lock().lines().map_while(Result::ok);
+            ascii::play(&mut state, lines, false, &mut stdout)
+        }
+    };
+
+    match result {
+        Ok(ascii::SessionEnd::Halted) => {}
+        Ok(ascii::SessionEnd::OutOfInput) => println!("[no more input after {} steps]", state.steps()),
+        Err(error) => return Err(error.to_string()),
+    }
+
+    Ok(())
+}
+
+/// Run a program, optionally patched with a noun and verb, writing a
+/// JSON Lines trace of every instruction to `trace_file`, or to stdout
+/// when it is `-`.
+///
+/// The trace of a program that fails is still written, up to where it failed.
+fn trace(filename: &str, trace_file: &str, patch: &[String]) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let patch = match patch {
+        [noun, verb] => match (noun.parse::<Cell>(), verb.parse::<Cell>()) {
+            (Ok(noun), Ok(verb)) => Some((noun, verb)),
+            _ => return Err(format!("Not a valid noun and verb: {} {}", noun, verb)),
+        },
+        _ => None,
+    };
+
+    if trace_file == "-" {
+        let mut tracer = tracer::MemoryTracer::new();
+        let result = run_traced(instructions, patch, &mut tracer);
+
+        print!("{}", tracer.to_json_lines());
+        return result;
+    }
+
+    let mut tracer = tracer::JsonLinesTracer::create(trace_file)
+        .map_err(|error| format!("Failed to create {}: {}", trace_file, error))?;
+
+    let result = run_traced(instructions, patch, &mut tracer);
+
+    tracer.finish().map_err(|error| format!("Failed to write {}: {}", trace_file, error))?;
+    result
+}
+
+fn run_traced(instructions: Vec<Cell>, patch: Option<(Cell, Cell)>, tracer: impl Tracer) -> Result<(), String> {
+    let mut state = ProgramState::from_vec(instructions);
+
+    if let Some((noun, verb)) = patch {
+        state.patch_noun_and_verb(noun, verb);
+    }
+
+    state.set_tracer(tracer);
+
+    // The summary goes to stderr so it can't end up in the trace.
+    //
+    state.execute().map_err(|error| format!("Execution failed: {}", error))?;
+    eprintln!("Halted after {} steps, position 0 = {}", state.steps(), state.read(0));
+
+    Ok(())
+}
+
+/// Find the phase settings that give the largest signal from a chain of
+/// amplifiers, one per comma separated phase.
+fn amplify(filename: &str, phases: &str, feedback: bool) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let phases: Vec<Cell> = phases.split(',')
+        .map(|phase| phase.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Not a valid list of phases: {}", phases))?;
+
+    let (settings, signal) = amplifier::best_phase_settings(&instructions, &phases, feedback)
+        .map_err(|error| format!("Amplification failed: {}", error))?;
+
+    println!("Phase settings {} give signal {}", assembler::to_program_text(&settings), signal);
+    Ok(())
+}
+
+/// Run a network of machines, reporting the first packet sent to the NAT
+/// and the first value the NAT wakes the network with twice in a row.
+fn run_network(filename: &str, size: Option<&String>) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let size = match size.map(|size| size.parse::<usize>()) {
+        None => network::DEFAULT_SIZE,
+        Some(Ok(size)) if size > 0 => size,
+        Some(_) => return Err(format!("Not a valid network size: {}", size.map(String::as_str).unwrap_or_default())),
+    };
+
+    let network_failed = |error: network::NetworkError| format!("Network failed: {}", error);
+
+    let packet = network::first_nat_packet(&instructions, size).map_err(network_failed)?;
+    println!("First packet to the NAT has y = {}", packet.y);
+
+    let y = network::first_repeated_wake(&instructions, size).map_err(network_failed)?;
+    println!("First y the NAT sends twice in a row = {}", y);
+
+    Ok(())
+}
+
+/// Print an annotated listing of a program.
+fn disassemble(filename: &str) -> Result<(), String> {
+    print!("{}", disassembler::listing(&load_or_exit(filename)));
+    Ok(())
+}
+
+/// Print the control-flow graph of a program as Graphviz DOT, analysed from
+/// `entry` or address 0, with a summary on stderr.
+fn control_flow_graph(filename: &str, entry: Option<&String>) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let entry = match entry.map(|entry| entry.parse::<usize>()) {
+        None => 0,
+        Some(Ok(entry)) => entry,
+        Some(Err(_)) => return Err(format!("Not a valid entry point: {}", entry.unwrap())),
+    };
+
+    let graph = control_flow::ControlFlowGraph::build(&instructions, entry);
+    print!("{}", graph.to_dot());
+
+    eprintln!("{} blocks, {} self-modifying writes", graph.blocks.len(), graph.self_modifying.len());
+    for write in &graph.self_modifying {
+        eprintln!("  {:04} writes code at {:04}", write.instruction, write.target);
+    }
+
+    Ok(())
+}
+
+/// Assemble a source file and print the program in loadable form.
+fn assemble(filename: &str) -> Result<(), String> {
+    let source = std::fs::read_to_string(filename).map_err(|error| format!("Failed to load {}: {}", filename, error))?;
+    let image = assembler::assemble(&source).map_err(|error| format!("{}: {}", filename, error))?;
+
+    println!("{}", assembler::to_program_text(&image));
+    Ok(())
+}
+
+/// Load a program and drop into the step debugger before its first instruction.
+fn debug(filename: &str, inputs: &[String]) -> Result<(), String> {
+    let instructions = load_or_exit(filename);
+
+    let mut state = ProgramState::from_vec(instructions);
+    push_inputs(&mut state, inputs)?;
+
+    state.set_input_device(ConsoleInput);
+    state.set_output_device(ConsoleOutput);
+
+    let mut debugger = debugger::Debugger::new(state);
+    debugger.run(debugger::stdin_commands(), &mut io::stdout()).map_err(|error| format!("Debugger failed: {}", error))
+}
+
+/// The number of addresses the profile table shows.
+const HOT_SPOTS: usize = 20;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let result = match args.as_slice() {
+        [_, command, filename, inputs @ ..] if command == "run" => run_interactive(filename, inputs),
+        [_, command, filename, inputs @ ..] if command == "resume" => resume(filename, inputs),
+        [_, command, filename, inputs @ ..] if command == "debug" => debug(filename, inputs),
+        [_, command, filename, format, inputs @ ..] if command == "profile" && (format == "table" || format == "csv") => {
+            profile(filename, format, inputs)
+        }
+        [_, command, filename, trace_file, patch @ ..] if command == "trace" && patch.len() % 2 == 0 && patch.len() <= 2 => {
+            trace(filename, trace_file, patch)
+        }
+        [_, command, filename] if command == "disasm" => disassemble(filename),
+        [_, command, filename] if command == "asm" => assemble(filename),
+        [_, command, filename, entry @ ..] if command == "cfg" && entry.len() <= 1 => control_flow_graph(filename, entry.first()),
+        [_, command, filename, phases] if command == "amp" => amplify(filename, phases, false),
+        [_, command, filename, phases, mode] if command == "amp" && mode == "feedback" => amplify(filename, phases, true),
+        [_, command, filename, transcript @ ..] if command == "ascii" && transcript.len() <= 1 => run_ascii(filename, transcript.first()),
+        [_, command, filename, size @ ..] if command == "network" && size.len() <= 1 => run_network(filename, size.first()),
+        _ => {
+            eprint!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    // Report failures where scripts look for them, so a bad program can be
+    // told apart from a good one by the exit status alone.
+    //
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,128 @@
+
+/*!
+The memory of an Intcode machine, addressed from 0 to the top of `usize`.
+*/
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use crate::cell::Cell;
+
+/// Intcode program memory.
+///
+/// The loaded program image is kept contiguous, while any address written
+/// beyond the end of the image is stored sparsely, so programs can use memory
+/// far past their initial length without allocating everything in between.
+/// Reading an address that has never been written returns zero.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Memory {
+    image: Vec<Cell>,
+    sparse: HashMap<usize, Cell>,
+}
+
+impl Memory {
+
+    /// Memory holding `image` from address 0, and zero everywhere else.
+    pub fn from_vec(image: Vec<Cell>) -> Self {
+        Self {
+            image,
+            sparse: HashMap::new(),
+        }
+    }
+
+    /// The value at `address`, zero if it has never been written.
+    pub fn read(&self, address: usize) -> Cell {
+        self.image.get(address)
+            .or_else(|| self.sparse.get(&address))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Write `value` to `address`, anywhere in the address space.
+    pub fn write(&mut self, address: usize, value: Cell) {
+        match self.image.get_mut(address) {
+            Some(cell) => *cell = value,
+            None => {
+                self.sparse.insert(address, value);
+            }
+        }
+    }
+
+    /// The value at `address`, or `None` past the image where nothing has
+    /// been written.
+    pub fn get(&self, address: usize) -> Option<&Cell> {
+        self.image.get(address).or_else(|| self.sparse.get(&address))
+    }
+
+    /// Forget a write past the image, as if the address had never been
+    /// written. Addresses in the image are left alone.
+    pub fn forget(&mut self, address: usize) {
+        self.sparse.remove(&address);
+    }
+
+    /// The loaded program image, including any writes made into it.
+    pub fn image(&self) -> &[Cell] {
+        &self.image
+    }
+
+    /// The cells written beyond the end of the image, in address order.
+    pub fn sparse(&self) -> impl Iterator<Item = (usize, &Cell)> {
+        let mut cells: Vec<(usize, &Cell)> = self.sparse.iter().map(|(address, value)| (*address, value)).collect();
+        cells.sort_by_key(|(address, _)| *address);
+        cells.into_iter()
+    }
+}
+
+/// Equal memories hash equally, whatever order the sparse cells were written in.
+impl Hash for Memory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.image.hash(state);
+        for cell in self.sparse() {
+            cell.hash(state);
+        }
+    }
+}
+
+#[test]
+fn test_memory_read_write() {
+    use crate::cell;
+
+    let mut memory = Memory::from_vec(cell::from_slice(&[1, 2, 3]));
+
+    memory.write(1, Cell::from(20));
+    assert_eq!(memory.read(1), Cell::from(20));
+    assert_eq!(memory.image(), cell::from_slice(&[1, 20, 3]).as_slice());
+
+    // Untouched memory past the image reads as zero.
+    //
+    assert_eq!(memory.read(3), Cell::from(0));
+    assert_eq!(memory.read(1 << 40), Cell::from(0));
+
+    // Writes past the image don't grow it.
+    //
+    memory.write(1 << 40, Cell::from(7));
+    memory.write(5, Cell::from(6));
+    assert_eq!(memory.read(1 << 40), Cell::from(7));
+    assert_eq!(memory.image(), cell::from_slice(&[1, 20, 3]).as_slice());
+
+    let sparse: Vec<(usize, &Cell)> = memory.sparse().collect();
+    assert_eq!(sparse, vec![(5, &Cell::from(6)), (1 << 40, &Cell::from(7))]);
+}
+
+#[test]
+fn test_memory_get_and_forget() {
+    use crate::cell;
+
+    let mut memory = Memory::from_vec(cell::from_slice(&[1, 2]));
+    memory.write(10, Cell::from(3));
+
+    assert_eq!(memory.get(1), Some(&Cell::from(2)));
+    assert_eq!(memory.get(10), Some(&Cell::from(3)));
+    assert_eq!(memory.get(11), None);
+
+    memory.forget(1);
+    memory.forget(10);
+    assert_eq!(memory.get(1), Some(&Cell::from(2)));
+    assert_eq!(memory.get(10), None);
+    assert_eq!(memory, Memory::from_vec(cell::from_slice(&[1, 2])));
+}
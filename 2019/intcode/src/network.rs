@@ -0,0 +1,322 @@
+
+/*!
+A network of Intcode machines exchanging packets.
+
+Every machine runs its own copy of one program, and is given its network
+address as its first input. A machine sends a packet by outputting three
+values: the destination address, then `x` and `y`. Packets are queued at
+the destination, and a machine reading input receives the `x` and `y` of
+its next packet, or `-1` when nothing is waiting.
+
+Machines are scheduled cooperatively, each running until it next waits for
+input. Packets sent to address 255 go to the NAT, which keeps only the last
+one. Once a full round passes in which no machine had a packet waiting and
+none sent one, the network is idle, and the NAT wakes it by sending its
+packet to address 0.
+*/
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::cell::{self, Cell};
+use crate::error::ExecError;
+use crate::{ProgramState, RunStatus};
+
+/// The address packets are sent to for the NAT.
+pub const NAT_ADDRESS: usize = 255;
+
+/// The number of machines in the puzzle's network.
+pub const DEFAULT_SIZE: usize = 50;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub destination: usize,
+    pub x: Cell,
+    pub y: Cell,
+}
+
+/// Something that happened on the network during a round.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkEvent {
+
+    // A machine sent a packet, to another machine or to the NAT.
+    //
+    Sent(Packet),
+
+    // The network was idle, so the NAT sent its packet to address 0.
+    //
+    Wake(Packet),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetworkError {
+
+    // The machine at the address failed to execute.
+    //
+    Exec { machine: usize, error: ExecError },
+
+    // A machine sent a packet to an address with nothing there.
+    //
+    BadDestination { machine: usize, destination: Cell },
+
+    // The network went idle before the NAT had a packet to wake it with.
+    //
+    Idle,
+
+    // Every machine has halted.
+    //
+    Halted,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Exec { machine, error } => write!(f, "machine {} failed: {}", machine, error),
+            NetworkError::BadDestination { machine, destination } => {
+                write!(f, "machine {} sent a packet to unknown address {}", machine, destination)
+            }
+            NetworkError::Idle => write!(f, "the network is idle and the NAT has no packet"),
+            NetworkError::Halted => write!(f, "every machine has halted"),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+struct Machine<'a> {
+    state: ProgramState<'a>,
+    halted: bool,
+
+    // Packets waiting to be read, as their `x` and `y` values.
+    //
+    queue: VecDeque<(Cell, Cell)>,
+
+    // Values output towards a packet that isn't complete yet.
+    //
+    partial: Vec<Cell>,
+}
+
+/// A cluster of machines passing packets to one another.
+pub struct Network<'a> {
+    machines: Vec<Machine<'a>>,
+    nat: Option<Packet>,
+}
+
+impl<'a> Network<'a> {
+
+    /// Boot `size` machines running `program`, with addresses from 0.
+    pub fn new(program: &[Cell], size: usize) -> Self {
+        let machines = (0..size)
+            .map(|address| {
+                let mut state = ProgramState::from_vec(program.to_vec());
+                state.push_input(cell::from_i64(address as i64));
+
+                Machine { state, halted: false, queue: VecDeque::new(), partial: Vec::new() }
+            })
+            .collect();
+
+        Self { machines, nat: None }
+    }
+
+    /// The packet the NAT is holding, if any.
+    pub fn nat(&self) -> Option<&Packet> {
+        self.nat.as_ref()
+    }
+
+    /// Give every machine one turn, then wake the network from the NAT if
+    /// it has gone idle. Returns what happened, in order.
+    pub fn round(&mut self) -> Result<Vec<NetworkEvent>, NetworkError> {
+        let mut events = Vec::new();
+        let mut idle = true;
+
+        for address in 0..self.machines.len() {
+            let machine = &mut self.machines[address];
+            if machine.halted {
+                continue;
+            }
+
+            match machine.queue.pop_front() {
+                Some((x, y)) => {
+                    idle = false;
+                    machine.state.push_input(x);
+                    machine.state.push_input(y);
+                }
+                None => machine.state.push_input(Cell::from(-1)),
+            }
+
+            for packet in self.run_machine(address)? {
+                idle = false;
+
+                if packet.destination == NAT_ADDRESS {
+                    self.nat = Some(packet.clone());
+                } else {
+                    self.machines[packet.destination].queue.push_back((packet.x.to_owned(), packet.y.to_owned()));
+                }
+
+                events.push(NetworkEvent::Sent(packet));
+            }
+        }
+
+        if self.machines.iter().all(|machine| machine.halted) {
+            return Err(NetworkError::Halted);
+        }
+
+        if idle {
+            let packet = self.nat.as_ref().ok_or(NetworkError::Idle)?;
+            let wake = Packet { destination: 0, x: packet.x.to_owned(), y: packet.y.to_owned() };
+
+            self.machines[0].queue.push_back((wake.x.to_owned(), wake.y.to_owned()));
+            events.push(NetworkEvent::Wake(wake));
+        }
+
+        Ok(events)
+    }
+
+    /// Run the machine at `address` until it waits for input or halts,
+    /// returning the packets it sent.
+    fn run_machine(&mut self, address: usize) -> Result<Vec<Packet>, NetworkError> {
+        let size = self.machines.len();
+        let machine = &mut self.machines[address];
+        let mut packets = Vec::new();
+
+        loop {
+            match machine.state.run() {
+                Ok(RunStatus::Output(value)) => machine.partial.push(value),
+                Ok(RunStatus::NeedsInput) => break,
+                Ok(RunStatus::Halted) => {
+                    machine.halted = true;
+                    break;
+                }
+                Err(error) => return Err(NetworkError::Exec { machine: address, error }),
+            }
+
+            if machine.partial.len() == 3 {
+                let mut values = machine.partial.drain(..);
+                let destination = values.next().unwrap_or_default();
+                let x = values.next().unwrap_or_default();
+                let y = values.next().unwrap_or_default();
+
+                let destination = match cell::to_usize(&destination) {
+                    Some(destination) if destination < size || destination == NAT_ADDRESS => destination,
+                    _ => return Err(NetworkError::BadDestination { machine: address, destination }),
+                };
+
+                packets.push(Packet { destination, x, y });
+            }
+        }
+
+        Ok(packets)
+    }
+}
+
+/// The first packet sent to the NAT.
+pub fn first_nat_packet(program: &[Cell], size: usize) -> Result<Packet, NetworkError> {
+    let mut network = Network::new(program, size);
+
+    loop {
+        for event in network.round()? {
+            if let NetworkEvent::Sent(packet) = event {
+                if packet.destination == NAT_ADDRESS {
+                    return Ok(packet);
+                }
+            }
+        }
+    }
+}
+
+/// The first `y` the NAT sends to address 0 twice in a row.
+pub fn first_repeated_wake(program: &[Cell], size: usize) -> Result<Cell, NetworkError> {
+    let mut network = Network::new(program, size);
+    let mut last_y = None;
+
+    loop {
+        for event in network.round()? {
+            if let NetworkEvent::Wake(packet) = event {
+                if last_y.as_ref() == Some(&packet.y) {
+                    return Ok(packet.y);
+                }
+
+                last_y = Some(packet.y);
+            }
+        }
+    }
+}
+
+/// A machine that passes each packet on to the next address, and the last
+/// machine of three passes it to the NAT. Address 0 starts things off.
+#[cfg(test)]
+const RELAY: &str = "
+            IN [addr]
+            JNZ [addr], listen
+            OUT 1
+            OUT 7
+            OUT 100
+    listen: IN [x]
+            EQ [x], -1, [idle]
+            JNZ [idle], listen
+            IN [y]
+            ADD [addr], 1, [dest]
+            EQ [dest], 3, [last]
+            JZ [last], send
+            ADD 255, 0, [dest]
+    send:   OUT [dest]
+            OUT [x]
+            OUT [y]
+            JZ 0, listen
+    addr:   DATA 0
+    x:      DATA 0
+    y:      DATA 0
+    dest:   DATA 0
+    idle:   DATA 0
+    last:   DATA 0
+";
+
+#[test]
+fn test_network_relay() {
+    let program = crate::assembler::assemble(RELAY).unwrap();
+
+    assert_eq!(first_nat_packet(&program, 3),
+        Ok(Packet { destination: NAT_ADDRESS, x: Cell::from(7), y: Cell::from(100) }));
+
+    let mut network = Network::new(&program, 3);
+    let sent = |events: Vec<NetworkEvent>| -> Vec<usize> {
+        events.iter()
+            .filter_map(|event| match event {
+                NetworkEvent::Sent(packet) => Some(packet.destination),
+                NetworkEvent::Wake(_) => None,
+            })
+            .collect()
+    };
+
+    // Each round the packet moves one machine along, until the NAT has it,
+    // then the next round is idle and the NAT wakes address 0.
+    //
+    assert_eq!(sent(network.round().unwrap()), vec![1, 2, NAT_ADDRESS]);
+    assert_eq!(network.nat(), Some(&Packet { destination: NAT_ADDRESS, x: Cell::from(7), y: Cell::from(100) }));
+
+    assert_eq!(network.round().unwrap(),
+        vec![NetworkEvent::Wake(Packet { destination: 0, x: Cell::from(7), y: Cell::from(100) })]);
+}
+
+#[test]
+fn test_network_repeated_wake() {
+    let program = crate::assembler::assemble(RELAY).unwrap();
+
+    // Address 0 only starts the packet off, so the NAT's packet comes back
+    // unchanged every time it wakes the network.
+    //
+    assert_eq!(first_repeated_wake(&program, 3), Ok(Cell::from(100)));
+}
+
+#[test]
+fn test_network_errors() {
+    use crate::cell::from_slice;
+
+    // Send to address 9 on a network of two.
+    //
+    assert_eq!(Network::new(&from_slice(&[3,100,104,9,104,0,104,0,99]), 2).round(),
+        Err(NetworkError::BadDestination { machine: 0, destination: Cell::from(9) }));
+
+    assert_eq!(first_nat_packet(&from_slice(&[3,100,3,100,1105,1,2]), 2), Err(NetworkError::Idle));
+    assert_eq!(first_nat_packet(&from_slice(&[3,100,99]), 2), Err(NetworkError::Halted));
+}
@@ -0,0 +1,200 @@
+
+/*!
+Per-address execution profiles of Intcode programs.
+
+With profiling enabled on a `ProgramState`, every executed instruction is
+counted at its address, along with every read a parameter makes from memory
+and every write it makes. Immediate parameters aren't reads, and fetching an
+instruction doesn't count as reading its cells.
+
+The report lists the hottest addresses first, so the loop that dominates a
+slow program is at the top of the table. Each address shows the instruction
+that first ran there, disassembled before it ran, so a self-modifying program
+is reported as it executed rather than as it finished:
+
+```text
+ address  executions   share   reads  writes  instruction
+    0002         101   25.1%       0       0  JZ [15], 14
+    0005         100   24.8%       0       0  OUT [15]
+    0007         100   24.8%       0       0  ADD [15], -1, [15]
+    0011         100   24.8%       0       0  JZ 0, 2
+    0015           0    0.0%     301     101
+```
+*/
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::disassembler::ListingLine;
+
+/// What happened at a single address.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AddressCounts {
+    pub executions: u64,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// The counts gathered while profiling a run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    counts: HashMap<usize, AddressCounts>,
+    instructions: HashMap<usize, ListingLine>,
+    steps: u64,
+}
+
+impl Profile {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count an instruction executed at `address`, keeping `instruction` as
+    /// it was before it ran if it's the first to run there.
+    pub fn record_execution(&mut self, address: usize, instruction: Option<ListingLine>) {
+        if let Some(instruction) = instruction {
+            self.instructions.entry(address).or_insert(instruction);
+        }
+
+        self.counts.entry(address).or_default().executions += 1;
+        self.steps += 1;
+    }
+
+    pub fn record_read(&mut self, address: usize) {
+        self.counts.entry(address).or_default().reads += 1;
+    }
+
+    pub fn record_write(&mut self, address: usize) {
+        self.counts.entry(address).or_default().writes += 1;
+    }
+
+    /// The number of instructions executed while profiling.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The counts for `address`, all zero if nothing touched it.
+    pub fn counts(&self, address: usize) -> AddressCounts {
+        self.counts.get(&address).copied().unwrap_or_default()
+    }
+
+    /// Every address touched, the most executed first, then the most
+    /// accessed, then in address order.
+    pub fn hot_spots(&self) -> Vec<(usize, AddressCounts)> {
+        let mut hot_spots: Vec<(usize, AddressCounts)> = self.counts.iter()
+            .map(|(address, counts)| (*address, *counts))
+            .collect();
+
+        hot_spots.sort_by_key(|(address, counts)| {
+            (std::cmp::Reverse(counts.executions), std::cmp::Reverse(counts.reads + counts.writes), *address)
+        });
+
+        hot_spots
+    }
+
+    /// The share of all executed instructions that ran at an address.
+    fn share(&self, counts: &AddressCounts) -> f64 {
+        match self.steps {
+            0 => 0.0,
+            steps => 100.0 * counts.executions as f64 / steps as f64,
+        }
+    }
+
+    /// The disassembled instruction that first ran at an address, if any did.
+    fn instruction(&self, address: usize) -> String {
+        self.instructions.get(&address).map(ListingLine::instruction).unwrap_or_default()
+    }
+
+    /// A table of the `limit` hottest addresses, with the instructions
+    /// executed there.
+    pub fn table(&self, limit: usize) -> String {
+        let mut table = String::new();
+        writeln!(table, "{:>8}  {:>10}  {:>6}  {:>6}  {:>6}  instruction", "address", "executions", "share", "reads", "writes").unwrap();
+
+        for (address, counts) in self.hot_spots().into_iter().take(limit) {
+            let instruction = self.instruction(address);
+            let row = format!("{:>8}  {:>10}  {:>5.1}%  {:>6}  {:>6}  {}",
+                format!("{:04}", address), counts.executions, self.share(&counts), counts.reads, counts.writes, instruction);
+
+            writeln!(table, "{}", row.trim_end()).unwrap();
+        }
+
+        table
+    }
+
+    /// Every address touched as CSV, hottest first, with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("address,executions,reads,writes,instruction\n");
+
+        for (address, counts) in self.hot_spots() {
+            let instruction = self.instruction(address);
+            writeln!(csv, "{},{},{},{},\"{}\"", address, counts.executions, counts.reads, counts.writes, instruction).unwrap();
+        }
+
+        csv
+    }
+}
+
+#[test]
+fn test_profile_counts() {
+    use crate::cell;
+
+    // Count [11] down from 3, then halt.
+    //
+    let mut program_state = crate::ProgramState::from_vec(cell::from_slice(&[1006,11,10,1001,11,-1,11,1105,1,0,99,3]));
+    program_state.enable_profiling();
+    program_state.execute().unwrap();
+
+    let profile = program_state.take_profile().unwrap();
+    assert_eq!(profile.steps(), 11);
+    assert_eq!(profile.counts(0), AddressCounts { executions: 4, reads: 0, writes: 0 });
+    assert_eq!(profile.counts(3), AddressCounts { executions: 3, reads: 0, writes: 0 });
+    assert_eq!(profile.counts(11), AddressCounts { executions: 0, reads: 7, writes: 3 });
+    assert_eq!(profile.counts(10), AddressCounts { executions: 1, reads: 0, writes: 0 });
+
+    let hot: Vec<usize> = profile.hot_spots().iter().map(|(address, _)| *address).collect();
+    assert_eq!(hot, vec![0, 3, 7, 10, 11]);
+
+    assert_eq!(program_state.take_profile(), None);
+}
+
+#[test]
+fn test_profile_reports() {
+    use crate::cell;
+
+    let mut program_state = crate::ProgramState::from_vec(cell::from_slice(&[1,0,0,5,99,0]));
+    program_state.enable_profiling();
+    program_state.execute().unwrap();
+
+    let profile = program_state.take_profile().unwrap();
+
+    let table = [
+        " address  executions   share   reads  writes  instruction",
+        "    0000           1   50.0%       2       0  ADD [0], [0], [5]",
+        "    0004           1   50.0%       0       0  HLT",
+    ];
+
+    assert_eq!(profile.table(2), table.join("\n") + "\n");
+
+    assert_eq!(profile.to_csv(), "\
+address,executions,reads,writes,instruction
+0,1,2,0,\"ADD [0], [0], [5]\"
+4,1,0,0,\"HLT\"
+5,0,0,1,\"\"
+");
+}
+
+#[test]
+fn test_profile_self_modifying() {
+    use crate::cell;
+
+    // Read over the input instruction, then output what was read.
+    //
+    let mut program_state = crate::ProgramState::from_vec(cell::from_slice(&[3,0,4,0,99]));
+    program_state.push_input(cell::from_i64(7));
+    program_state.enable_profiling();
+    program_state.execute().unwrap();
+
+    let table = program_state.take_profile().unwrap().table(1);
+    assert_eq!(table.lines().nth(1), Some("    0000           1   33.3%       1       1  IN [0]"));
+}
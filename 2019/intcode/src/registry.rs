@@ -0,0 +1,202 @@
+
+/*!
+A runtime registry of opcodes, for extending the Intcode instruction set.
+
+A machine normally dispatches through `lookup_opcode`, which only knows the
+standard opcodes. Attaching a registry with `ProgramState::set_opcodes` makes
+it look up every instruction in the registry instead, so extensions like a
+debug print or a syscall opcode can be tried out without changing the
+interpreter:
+
+```text
+let mut opcodes = OpCodeRegistry::standard();
+opcodes.register(50, DebugPrintOpCode)?;
+state.set_opcodes(opcodes);
+```
+
+Each opcode brings its own arity through `OpCode::parameter_count`, which
+registering checks fits in an instruction. Instructions whose opcode isn't
+registered still fail with `ErrorKind::UnknownOpcode`.
+
+Only the machine uses the registry. The assembler, disassembler and symbolic
+execution know the standard opcodes alone.
+*/
+
+use std::fmt;
+
+use crate::error::ErrorKind;
+use crate::{Instruction, OpCode, OPCODES};
+use crate::{AddOpCode, AdjustRelativeBaseOpCode, EqualsOpCode, HaltOpCode, InputOpCode, JumpIfFalseOpCode};
+use crate::{JumpIfTrueOpCode, LessThanOpCode, MultOpCode, OutputOpCode};
+
+/// Opcodes are the last two decimal digits of an instruction.
+const OPCODE_LIMIT: u32 = 100;
+
+/// Why an opcode couldn't be registered.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegistryError {
+
+    // The code doesn't fit in the two digits an instruction has for it.
+    //
+    BadCode(u32),
+
+    // The opcode takes more parameters than an instruction has modes for.
+    //
+    TooManyParameters { code: u32, count: usize },
+
+    // Another opcode is already registered under the code.
+    //
+    AlreadyRegistered(u32),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::BadCode(code) => write!(f, "opcode {} isn't between 0 and {}", code, OPCODE_LIMIT - 1),
+            RegistryError::TooManyParameters { code, count } => {
+                write!(f, "opcode {} takes {} parameters, but an instruction has modes for {}",
+                    code, count, Instruction::MAX_PARAMETERS)
+            }
+            RegistryError::AlreadyRegistered(code) => write!(f, "opcode {} is already registered", code),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+/// Opcode implementations, indexed by their numeric code.
+pub struct OpCodeRegistry<'a> {
+    opcodes: Vec<Option<Box<dyn OpCode + 'a>>>,
+}
+
+impl<'a> Default for OpCodeRegistry<'a> {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
+
+impl<'a> OpCodeRegistry<'a> {
+
+    /// A registry with no opcodes at all, not even halt.
+    pub fn new() -> Self {
+        Self {
+            opcodes: (0..OPCODE_LIMIT).map(|_| None).collect(),
+        }
+    }
+
+    /// A registry with the standard opcodes, the same ones `lookup_opcode`
+    /// knows.
+    pub fn standard() -> Self {
+        let mut registry = Self::new();
+        let standard: [Box<dyn OpCode>; 10] = [
+            Box::new(AddOpCode),
+            Box::new(MultOpCode),
+            Box::new(InputOpCode),
+            Box::new(OutputOpCode),
+            Box::new(JumpIfTrueOpCode),
+            Box::new(JumpIfFalseOpCode),
+            Box::new(LessThanOpCode),
+            Box::new(EqualsOpCode),
+            Box::new(AdjustRelativeBaseOpCode),
+            Box::new(HaltOpCode),
+        ];
+
+        for (code, op_code) in OPCODES.iter().zip(standard) {
+            registry.opcodes[*code as usize] = Some(op_code);
+        }
+
+        registry
+    }
+
+    /// Register `op_code` under `code`, which must be free.
+    pub fn register(&mut self, code: u32, op_code: impl OpCode + 'a) -> Result<(), RegistryError> {
+        if code >= OPCODE_LIMIT {
+            return Err(RegistryError::BadCode(code));
+        }
+
+        let count = op_code.parameter_count();
+        if count > Instruction::MAX_PARAMETERS {
+            return Err(RegistryError::TooManyParameters { code, count });
+        }
+
+        let slot = &mut self.opcodes[code as usize];
+        if slot.is_some() {
+            return Err(RegistryError::AlreadyRegistered(code));
+        }
+
+        *slot = Some(Box::new(op_code));
+        Ok(())
+    }
+
+    /// Remove the opcode registered under `code`, returning whether there was
+    /// one. Replacing an opcode means unregistering it first.
+    pub fn unregister(&mut self, code: u32) -> bool {
+        match self.opcodes.get_mut(code as usize) {
+            Some(slot) => slot.take().is_some(),
+            None => false,
+        }
+    }
+
+    /// Look up the implementation of an opcode.
+    pub fn lookup(&self, code: u32) -> Result<&(dyn OpCode + 'a), ErrorKind> {
+        match self.opcodes.get(code as usize) {
+            Some(Some(op_code)) => Ok(op_code.as_ref()),
+            _ => Err(ErrorKind::UnknownOpcode),
+        }
+    }
+
+    /// Every registered code, in ascending order.
+    pub fn codes(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..OPCODE_LIMIT).filter(move |code| self.opcodes[*code as usize].is_some())
+    }
+}
+
+#[test]
+fn test_registry_standard() {
+    let registry = OpCodeRegistry::standard();
+    assert_eq!(registry.codes().collect::<Vec<u32>>(), OPCODES.to_vec());
+
+    for code in OPCODES.iter() {
+        let expected = crate::lookup_opcode(*code).map(|op_code| (op_code.mnemonic(), op_code.parameter_count()));
+        let registered = registry.lookup(*code).map(|op_code| (op_code.mnemonic(), op_code.parameter_count()));
+        assert_eq!(registered, expected);
+    }
+
+    assert_eq!(registry.lookup(42).err(), Some(ErrorKind::UnknownOpcode));
+    assert_eq!(OpCodeRegistry::new().lookup(99).err(), Some(ErrorKind::UnknownOpcode));
+}
+
+#[test]
+fn test_registry_errors() {
+    struct WideOpCode;
+    impl OpCode for WideOpCode {
+        fn parameter_count(&self) -> usize {
+            4
+        }
+
+        fn mnemonic(&self) -> &'static str {
+            "WIDE"
+        }
+
+        fn execute(&self, _state: &mut crate::ProgramState) -> Result<crate::Outcome, ErrorKind> {
+            Ok(crate::Outcome::Continue)
+        }
+    }
+
+    let mut registry = OpCodeRegistry::standard();
+
+    assert_eq!(registry.register(100, HaltOpCode), Err(RegistryError::BadCode(100)));
+    assert_eq!(registry.register(50, WideOpCode), Err(RegistryError::TooManyParameters { code: 50, count: 4 }));
+    assert_eq!(registry.register(1, MultOpCode), Err(RegistryError::AlreadyRegistered(1)));
+
+    assert_eq!(RegistryError::TooManyParameters { code: 50, count: 4 }.to_string(),
+        "opcode 50 takes 4 parameters, but an instruction has modes for 3");
+
+    // Swap addition for multiplication.
+    //
+    assert!(registry.unregister(1));
+    assert!(!registry.unregister(1));
+    assert!(!registry.unregister(1000));
+    assert_eq!(registry.register(1, MultOpCode), Ok(()));
+    assert_eq!(registry.lookup(1).map(|op_code| op_code.mnemonic()).ok(), Some("MUL"));
+}
@@ -0,0 +1,251 @@
+
+/*!
+Saving and restoring a paused Intcode machine.
+
+A snapshot holds everything needed to resume a program exactly where it
+stopped: memory, the instruction pointer, the relative base, the step count,
+and any input or output still queued. Attached devices and tracers aren't
+part of the machine, so they are left for the caller to set up again.
+
+Snapshots are stored as text, one field per line after a version header:
+
+```text
+intcode-snapshot 1
+ip 4
+rb 0
+steps 1
+image 1,9,10,70,2,3,11,0,99,30,40,50
+sparse 1000=7
+input 5,6
+output
+```
+
+Lists are comma separated and may be empty. Sparse memory is stored as
+`address=value` pairs for the cells written beyond the end of the image.
+*/
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::cell::Cell;
+use crate::memory::Memory;
+
+/// The header line every snapshot starts with, minus the version.
+const HEADER: &str = "intcode-snapshot";
+
+/// The snapshot format version written by `to_text`.
+const VERSION: u32 = 1;
+
+/// A problem found while parsing a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SnapshotError {
+
+    // The header is missing, or names a version this build can't read.
+    //
+    UnsupportedVersion(String),
+
+    // A line, given by its 1 based number, couldn't be parsed.
+    //
+    Malformed { line: usize, text: String },
+
+    // A required field never appeared.
+    //
+    MissingField(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::UnsupportedVersion(header) => write!(f, "unsupported snapshot header '{}'", header),
+            SnapshotError::Malformed { line, text } => write!(f, "line {}: can't parse '{}'", line, text),
+            SnapshotError::MissingField(field) => write!(f, "missing field '{}'", field),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// The complete state of a paused machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub instruction_pointer: usize,
+    pub relative_base: Cell,
+    pub steps: u64,
+    pub memory: Memory,
+    pub input: Vec<Cell>,
+    pub output: Vec<Cell>,
+}
+
+fn join(values: &[Cell]) -> String {
+    let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+    values.join(",")
+}
+
+fn parse_list<T: std::str::FromStr>(text: &str) -> Option<Vec<T>> {
+    if text.is_empty() {
+        return Some(Vec::new());
+    }
+
+    text.split(',').map(|value| value.trim().parse().ok()).collect()
+}
+
+fn parse_sparse(text: &str) -> Option<Vec<(usize, Cell)>> {
+    if text.is_empty() {
+        return Some(Vec::new());
+    }
+
+    text.split(',')
+        .map(|pair| {
+            let mut parts = pair.trim().splitn(2, '=');
+            let address = parts.next()?.parse().ok()?;
+            let value = parts.next()?.parse().ok()?;
+            Some((address, value))
+        })
+        .collect()
+}
+
+impl Snapshot {
+
+    /// Format the snapshot in the current version of the text format.
+    pub fn to_text(&self) -> String {
+        let sparse: Vec<String> = self.memory.sparse()
+            .map(|(address, value)| format!("{}={}", address, value))
+            .collect();
+
+        let fields = [
+            (HEADER, VERSION.to_string()),
+            ("ip", self.instruction_pointer.to_string()),
+            ("rb", self.relative_base.to_string()),
+            ("steps", self.steps.to_string()),
+            ("image", join(self.memory.image())),
+            ("sparse", sparse.join(",")),
+            ("input", join(&self.input)),
+            ("output", join(&self.output)),
+        ];
+
+        // Empty lists leave just the field name, without a trailing space.
+        //
+        fields.iter()
+            .map(|(field, value)| format!("{} {}", field, value).trim_end().to_string() + "\n")
+            .collect()
+    }
+
+    /// Parse a snapshot written by `to_text`.
+    pub fn parse(text: &str) -> Result<Self, SnapshotError> {
+        let mut lines = text.lines().enumerate();
+
+        let header = lines.next().map(|(_, line)| line.trim()).unwrap_or("");
+        if header != format!("{} {}", HEADER, VERSION) {
+            return Err(SnapshotError::UnsupportedVersion(header.to_string()));
+        }
+
+        let mut instruction_pointer = None;
+        let mut relative_base = None;
+        let mut steps = None;
+        let mut image = None;
+        let mut sparse = None;
+        let mut input = None;
+        let mut output = None;
+
+        for (index, line) in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (field, value) = match line.find(' ') {
+                Some(split) => (&line[..split], line[split + 1..].trim()),
+                None => (line, ""),
+            };
+
+            let parsed = match field {
+                "ip" => value.parse().ok().map(|value| instruction_pointer = Some(value)),
+                "rb" => value.parse().ok().map(|value| relative_base = Some(value)),
+                "steps" => value.parse().ok().map(|value| steps = Some(value)),
+                "image" => parse_list(value).map(|value| image = Some(value)),
+                "sparse" => parse_sparse(value).map(|value| sparse = Some(value)),
+                "input" => parse_list(value).map(|value| input = Some(value)),
+                "output" => parse_list(value).map(|value| output = Some(value)),
+                _ => None,
+            };
+
+            if parsed.is_none() {
+                return Err(SnapshotError::Malformed { line: index + 1, text: line.to_string() });
+            }
+        }
+
+        let mut memory = Memory::from_vec(image.ok_or(SnapshotError::MissingField("image"))?);
+
+        for (address, value) in sparse.unwrap_or_default() {
+            memory.write(address, value);
+        }
+
+        Ok(Self {
+            instruction_pointer: instruction_pointer.ok_or(SnapshotError::MissingField("ip"))?,
+            relative_base: relative_base.ok_or(SnapshotError::MissingField("rb"))?,
+            steps: steps.unwrap_or(0),
+            memory,
+            input: input.unwrap_or_default(),
+            output: output.unwrap_or_default(),
+        })
+    }
+
+    /// Write the snapshot to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    /// Read a snapshot from `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+
+        Self::parse(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    use crate::cell;
+
+    // Pause a program that adds its two inputs part way through.
+    //
+    let mut program_state = crate::ProgramState::from_vec(cell::from_slice(&[3,100,3,101,1,100,101,1000,4,1000,99]));
+    program_state.push_input(Cell::from(30));
+    program_state.push_input(Cell::from(12));
+    program_state.step().unwrap();
+
+    let text = program_state.snapshot().to_text();
+    assert_eq!(text, "\
+intcode-snapshot 1
+ip 2
+rb 0
+steps 1
+image 3,100,3,101,1,100,101,1000,4,1000,99
+sparse 100=30
+input 12
+output
+");
+
+    let mut restored = crate::ProgramState::from_snapshot(Snapshot::parse(&text).unwrap());
+    assert_eq!(restored.snapshot(), program_state.snapshot());
+
+    restored.execute().unwrap();
+    program_state.execute().unwrap();
+
+    assert_eq!(restored.snapshot(), program_state.snapshot());
+    assert_eq!(restored.take_output(), cell::from_slice(&[42]));
+}
+
+#[test]
+fn test_snapshot_errors() {
+    assert_eq!(Snapshot::parse("intcode-snapshot 2\nip 0"),
+        Err(SnapshotError::UnsupportedVersion("intcode-snapshot 2".to_string())));
+
+    assert_eq!(Snapshot::parse("intcode-snapshot 1\nip 0\nrb 0\nimage 1,x"),
+        Err(SnapshotError::Malformed { line: 4, text: "image 1,x".to_string() }));
+
+    assert_eq!(Snapshot::parse("intcode-snapshot 1\nip 0\nimage 99"),
+        Err(SnapshotError::MissingField("rb")));
+}
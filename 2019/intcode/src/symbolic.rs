@@ -0,0 +1,585 @@
+
+/*!
+Symbolic execution over patched memory cells.
+
+Instead of running a program once per candidate patch, the patched cells are
+treated as variables and every cell computed from them holds a polynomial
+over those variables. For day 2 this turns address 0 into something like
+
+```text
+230400*[1] + [2] + 797870
+```
+
+which can be solved for any target directly, rather than by brute force.
+
+Add and multiply work on polynomials. Everything else an instruction needs to
+know exactly, like the opcode itself, a write address, a jump condition or a
+comparison, has to be a constant; if it depends on the patched cells the run
+fails with `Unresolvable`. Reading through an address that depends on the
+patched cells is allowed, but the value read is unknown, and it's an error
+to need that value later. Input and output aren't supported.
+*/
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::cell::{self, Cell};
+use crate::error::{ErrorKind, ExecError};
+use crate::memory::Memory;
+use crate::{lookup_opcode, Instruction, ParameterMode};
+
+/// The most instructions a symbolic run executes before giving up.
+const STEP_LIMIT: u64 = 1_000_000;
+
+/// A polynomial with integer coefficients over the patched cells.
+///
+/// Each term maps the power of every variable, in the order the cells were
+/// patched, to its coefficient. Terms with a zero coefficient aren't stored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    terms: BTreeMap<Vec<u32>, Cell>,
+}
+
+impl Polynomial {
+
+    pub fn constant(value: Cell) -> Self {
+        let mut terms = BTreeMap::new();
+        if value != Cell::from(0) {
+            terms.insert(Vec::new(), value);
+        }
+
+        Self { terms }
+    }
+
+    /// The polynomial that is just the variable at `index`.
+    pub fn variable(index: usize) -> Self {
+        let mut powers = vec![0; index + 1];
+        powers[index] = 1;
+
+        let mut terms = BTreeMap::new();
+        terms.insert(powers, Cell::from(1));
+
+        Self { terms }
+    }
+
+    /// The value of the polynomial, if it doesn't depend on any variable.
+    pub fn as_constant(&self) -> Option<Cell> {
+        match self.terms.iter().next() {
+            None => Some(Cell::from(0)),
+            Some((powers, value)) if self.terms.len() == 1 && powers.is_empty() => Some(value.to_owned()),
+            _ => None,
+        }
+    }
+
+    /// The highest power of the variable at `index` in any term.
+    pub fn degree_in(&self, index: usize) -> u32 {
+        self.terms.keys().map(|powers| powers.get(index).copied().unwrap_or(0)).max().unwrap_or(0)
+    }
+
+    fn add_term(terms: &mut BTreeMap<Vec<u32>, Cell>, powers: Vec<u32>, value: Cell) -> Option<()> {
+        let sum = match terms.get(&powers) {
+            Some(existing) => cell::checked_add(existing, &value)?,
+            None => value,
+        };
+
+        if sum == Cell::from(0) {
+            terms.remove(&powers);
+        } else {
+            terms.insert(powers, sum);
+        }
+
+        Some(())
+    }
+
+    /// Powers are stored without trailing zeros, so equal terms compare equal.
+    fn normalize(mut powers: Vec<u32>) -> Vec<u32> {
+        while powers.last() == Some(&0) {
+            powers.pop();
+        }
+
+        powers
+    }
+
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let mut terms = self.terms.clone();
+
+        for (powers, value) in &other.terms {
+            Self::add_term(&mut terms, powers.clone(), value.to_owned())?;
+        }
+
+        Some(Self { terms })
+    }
+
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mut terms = BTreeMap::new();
+
+        for (lhs_powers, lhs) in &self.terms {
+            for (rhs_powers, rhs) in &other.terms {
+                let length = lhs_powers.len().max(rhs_powers.len());
+                let powers = (0..length)
+                    .map(|index| lhs_powers.get(index).unwrap_or(&0) + rhs_powers.get(index).unwrap_or(&0))
+                    .collect();
+
+                Self::add_term(&mut terms, Self::normalize(powers), cell::checked_mul(lhs, rhs)?)?;
+            }
+        }
+
+        Some(Self { terms })
+    }
+
+    /// Evaluate with every variable given a value.
+    pub fn evaluate(&self, values: &[Cell]) -> Option<Cell> {
+        let mut total = Cell::from(0);
+
+        for (powers, coefficient) in &self.terms {
+            let mut term = coefficient.to_owned();
+
+            for (index, power) in powers.iter().enumerate() {
+                for _ in 0..*power {
+                    term = cell::checked_mul(&term, values.get(index)?)?;
+                }
+            }
+
+            total = cell::checked_add(&total, &term)?;
+        }
+
+        Some(total)
+    }
+
+    /// Format the polynomial, naming each variable by its patched address.
+    pub fn display(&self, addresses: &[usize]) -> String {
+        if self.terms.is_empty() {
+            return "0".to_string();
+        }
+
+        let mut text = String::new();
+
+        // Highest powers of the first variable come first.
+        //
+        for (powers, coefficient) in self.terms.iter().rev() {
+            let coefficient = coefficient.to_string();
+            let (negative, magnitude) = match coefficient.strip_prefix('-') {
+                Some(magnitude) => (true, magnitude),
+                None => (false, coefficient.as_str()),
+            };
+
+            let mut factors: Vec<String> = powers.iter()
+                .enumerate()
+                .filter(|(_, power)| **power > 0)
+                .map(|(index, power)| match power {
+                    1 => format!("[{}]", addresses[index]),
+                    _ => format!("[{}]^{}", addresses[index], power),
+                })
+                .collect();
+
+            if magnitude != "1" || factors.is_empty() {
+                factors.insert(0, magnitude.to_string());
+            }
+
+            text += match (text.is_empty(), negative) {
+                (true, false) => "",
+                (true, true) => "-",
+                (false, false) => " + ",
+                (false, true) => " - ",
+            };
+            text += &factors.join("*");
+        }
+
+        text
+    }
+}
+
+/// What a cell holds during a symbolic run.
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Known(Polynomial),
+
+    // Read through an address that depended on the patched cells.
+    //
+    Unknown,
+}
+
+/// Why a symbolic run failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SymbolicError {
+
+    // The program failed in the same way it would have when run normally.
+    //
+    Exec(ExecError),
+
+    // The instruction needs a value that depends on the patched cells.
+    //
+    Unresolvable { instruction_pointer: usize },
+
+    // The instruction does input or output, which isn't supported.
+    //
+    Unsupported { instruction_pointer: usize, mnemonic: &'static str },
+
+    // The value at the address was read through an address that depended
+    // on the patched cells.
+    //
+    UnknownValue(usize),
+
+    // The program ran too long without halting.
+    //
+    StepLimitExceeded(u64),
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::Exec(error) => write!(f, "{}", error),
+            SymbolicError::Unresolvable { instruction_pointer } => {
+                write!(f, "instruction at position {} depends on the patched cells", instruction_pointer)
+            }
+            SymbolicError::Unsupported { instruction_pointer, mnemonic } => {
+                write!(f, "{} at position {} isn't supported symbolically", mnemonic, instruction_pointer)
+            }
+            SymbolicError::UnknownValue(address) => {
+                write!(f, "value at position {} was read through a patched address", address)
+            }
+            SymbolicError::StepLimitExceeded(limit) => write!(f, "step limit of {} exceeded", limit),
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+/// Memory after a symbolic run, holding a polynomial in every cell computed
+/// from the patched cells.
+pub struct SymbolicMemory {
+    base: Memory,
+    cells: HashMap<usize, Value>,
+}
+
+impl SymbolicMemory {
+
+    fn read(&self, address: usize) -> Value {
+        match self.cells.get(&address) {
+            Some(value) => value.clone(),
+            None => Value::Known(Polynomial::constant(self.base.read(address))),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: Value) {
+        self.cells.insert(address, value);
+    }
+
+    /// The polynomial held at `address`.
+    pub fn value(&self, address: usize) -> Result<Polynomial, SymbolicError> {
+        match self.read(address) {
+            Value::Known(polynomial) => Ok(polynomial),
+            Value::Unknown => Err(SymbolicError::UnknownValue(address)),
+        }
+    }
+}
+
+/// The instruction being executed symbolically.
+struct Frame {
+    instruction_pointer: usize,
+    instruction: Cell,
+    modes: [ParameterMode; Instruction::MAX_PARAMETERS],
+}
+
+impl Frame {
+
+    fn error(&self, kind: ErrorKind) -> SymbolicError {
+        SymbolicError::Exec(ExecError {
+            kind,
+            instruction_pointer: self.instruction_pointer,
+            instruction: self.instruction.to_owned(),
+        })
+    }
+
+    fn unresolvable(&self) -> SymbolicError {
+        SymbolicError::Unresolvable { instruction_pointer: self.instruction_pointer }
+    }
+
+    /// The constant value of a polynomial this instruction depends on.
+    fn constant(&self, value: Value) -> Result<Cell, SymbolicError> {
+        match value {
+            Value::Known(polynomial) => polynomial.as_constant().ok_or_else(|| self.unresolvable()),
+            Value::Unknown => Err(self.unresolvable()),
+        }
+    }
+
+    fn to_address(&self, value: &Cell) -> Result<usize, SymbolicError> {
+        cell::to_usize(value).ok_or_else(|| self.error(ErrorKind::BadAddress(value.to_owned())))
+    }
+
+    /// The address a position or relative parameter refers to, or `None`
+    /// when it depends on the patched cells.
+    fn address(&self, memory: &SymbolicMemory, relative_base: &Cell, index: usize) -> Result<Option<usize>, SymbolicError> {
+        let parameter = match memory.read(self.instruction_pointer + index) {
+            Value::Known(polynomial) => match polynomial.as_constant() {
+                Some(parameter) => parameter,
+                None => return Ok(None),
+            },
+            Value::Unknown => return Ok(None),
+        };
+
+        let address = match self.modes[index - 1] {
+            ParameterMode::Position => parameter,
+            ParameterMode::Relative => {
+                cell::checked_add(relative_base, &parameter).ok_or_else(|| self.error(ErrorKind::Overflow))?
+            }
+            ParameterMode::Immediate => {
+                return Err(self.error(ErrorKind::BadParameterMode { parameter: index, mode: 1 }))
+            }
+        };
+
+        self.to_address(&address).map(Some)
+    }
+
+    fn get_arg(&self, memory: &SymbolicMemory, relative_base: &Cell, index: usize) -> Result<Value, SymbolicError> {
+        if self.modes[index - 1] == ParameterMode::Immediate {
+            return Ok(memory.read(self.instruction_pointer + index));
+        }
+
+        match self.address(memory, relative_base, index)? {
+            Some(address) => Ok(memory.read(address)),
+            None => Ok(Value::Unknown),
+        }
+    }
+
+    fn store_arg(&self, memory: &mut SymbolicMemory, relative_base: &Cell, index: usize, value: Value) -> Result<(), SymbolicError> {
+        match self.address(memory, relative_base, index)? {
+            Some(address) => {
+                memory.write(address, value);
+                Ok(())
+            }
+            None => Err(self.unresolvable()),
+        }
+    }
+}
+
+/// Run from `instruction_pointer` until the program halts, treating the
+/// cells at `patched` as variables, in order.
+pub fn execute(memory: &Memory, instruction_pointer: usize, relative_base: &Cell, patched: &[usize]) -> Result<SymbolicMemory, SymbolicError> {
+    let mut memory = SymbolicMemory { base: memory.clone(), cells: HashMap::new() };
+    let mut instruction_pointer = instruction_pointer;
+    let mut relative_base = relative_base.to_owned();
+
+    for (index, address) in patched.iter().enumerate() {
+        memory.write(*address, Value::Known(Polynomial::variable(index)));
+    }
+
+    for _ in 0..STEP_LIMIT {
+        let mut frame = Frame {
+            instruction_pointer,
+            instruction: Cell::from(0),
+            modes: [ParameterMode::Position; Instruction::MAX_PARAMETERS],
+        };
+
+        frame.instruction = frame.constant(memory.read(instruction_pointer))?;
+
+        let instruction = Instruction::decode(&frame.instruction).map_err(|kind| frame.error(kind))?;
+        let op_code = lookup_opcode(instruction.opcode).map_err(|kind| frame.error(kind))?;
+        frame.modes = instruction.modes;
+
+        let arg = |memory: &SymbolicMemory, relative_base: &Cell, index| frame.get_arg(memory, relative_base, index);
+        let mut next = instruction_pointer + 1 + op_code.parameter_count();
+
+        match instruction.opcode {
+            1 | 2 => {
+                let lhs = arg(&memory, &relative_base, 1)?;
+                let rhs = arg(&memory, &relative_base, 2)?;
+
+                let result = match (lhs, rhs) {
+                    (Value::Known(lhs), Value::Known(rhs)) => {
+                        let result = if instruction.opcode == 1 { lhs.checked_add(&rhs) } else { lhs.checked_mul(&rhs) };
+                        Value::Known(result.ok_or_else(|| frame.error(ErrorKind::Overflow))?)
+                    }
+                    _ => Value::Unknown,
+                };
+
+                frame.store_arg(&mut memory, &relative_base, 3, result)?;
+            }
+            5 | 6 => {
+                let condition = frame.constant(arg(&memory, &relative_base, 1)?)?;
+
+                if (condition != Cell::from(0)) == (instruction.opcode == 5) {
+                    let target = frame.constant(arg(&memory, &relative_base, 2)?)?;
+                    next = frame.to_address(&target)?;
+                }
+            }
+            7 | 8 => {
+                let lhs = frame.constant(arg(&memory, &relative_base, 1)?)?;
+                let rhs = frame.constant(arg(&memory, &relative_base, 2)?)?;
+                let result = if instruction.opcode == 7 { lhs < rhs } else { lhs == rhs };
+
+                let result = Polynomial::constant(Cell::from(if result { 1 } else { 0 }));
+                frame.store_arg(&mut memory, &relative_base, 3, Value::Known(result))?;
+            }
+            9 => {
+                let adjustment = frame.constant(arg(&memory, &relative_base, 1)?)?;
+                relative_base = cell::checked_add(&relative_base, &adjustment).ok_or_else(|| frame.error(ErrorKind::Overflow))?;
+            }
+            99 => return Ok(memory),
+            _ => return Err(SymbolicError::Unsupported { instruction_pointer, mnemonic: op_code.mnemonic() }),
+        }
+
+        instruction_pointer = next;
+    }
+
+    Err(SymbolicError::StepLimitExceeded(STEP_LIMIT))
+}
+
+/// Every assignment of the variables within `bounds` that makes the
+/// polynomial equal `target`, in ascending order.
+///
+/// All variables but the one with the lowest degree are enumerated. The
+/// remaining variable is solved for directly when the polynomial is linear in
+/// it, which covers day 2, and by evaluating across its bounds otherwise.
+pub fn solve(polynomial: &Polynomial, bounds: &[RangeInclusive<i64>], target: &Cell) -> Vec<Vec<Cell>> {
+    let mut solutions = Vec::new();
+
+    if bounds.is_empty() {
+        if polynomial.evaluate(&[]).as_ref() == Some(target) {
+            solutions.push(Vec::new());
+        }
+
+        return solutions;
+    }
+
+    let last = (0..bounds.len()).min_by_key(|index| polynomial.degree_in(*index)).unwrap_or(0);
+    let mut values: Vec<Cell> = bounds.iter().map(|range| cell::from_i64(*range.start())).collect();
+
+    // Step through every combination of the other variables like an odometer.
+    //
+    let others: Vec<usize> = (0..bounds.len()).filter(|index| *index != last).collect();
+    let mut current: Vec<i64> = others.iter().map(|index| *bounds[*index].start()).collect();
+
+    if others.iter().any(|index| bounds[*index].is_empty()) || bounds[last].is_empty() {
+        return solutions;
+    }
+
+    loop {
+        for (position, index) in others.iter().enumerate() {
+            values[*index] = cell::from_i64(current[position]);
+        }
+
+        solve_last(polynomial, &mut values, last, &bounds[last], target, &mut solutions);
+
+        let mut position = 0;
+        loop {
+            if position == others.len() {
+                solutions.sort();
+                return solutions;
+            }
+
+            if current[position] < *bounds[others[position]].end() {
+                current[position] += 1;
+                break;
+            }
+
+            current[position] = *bounds[others[position]].start();
+            position += 1;
+        }
+    }
+}
+
+/// Solve for the variable at `last` with every other variable fixed.
+fn solve_last(polynomial: &Polynomial, values: &mut [Cell], last: usize, range: &RangeInclusive<i64>, target: &Cell, solutions: &mut Vec<Vec<Cell>>) {
+    let mut candidates = Vec::new();
+
+    if polynomial.degree_in(last) == 1 {
+        // With the others fixed, the polynomial is `slope * x + offset`.
+        //
+        values[last] = Cell::from(0);
+        let offset = polynomial.evaluate(values);
+        values[last] = Cell::from(1);
+        let at_one = polynomial.evaluate(values);
+
+        if let (Some(offset), Some(at_one)) = (offset, at_one) {
+            let slope = cell::checked_sub(&at_one, &offset);
+
+            match slope {
+                Some(slope) if slope == Cell::from(0) && offset == *target => candidates.extend(range.clone()),
+                Some(slope) if slope == Cell::from(0) => {}
+                Some(slope) => {
+                    let root = cell::checked_sub(target, &offset).and_then(|difference| cell::exact_div(&difference, &slope));
+                    candidates.extend(root.as_ref().and_then(cell::to_i64).filter(|root| range.contains(root)));
+                }
+                None => {}
+            }
+        }
+    } else {
+        candidates.extend(range.clone());
+    }
+
+    for candidate in candidates {
+        values[last] = cell::from_i64(candidate);
+
+        if polynomial.evaluate(values).as_ref() == Some(target) {
+            solutions.push(values.to_vec());
+        }
+    }
+}
+
+#[test]
+fn test_polynomial_arithmetic() {
+    let x = Polynomial::variable(0);
+    let y = Polynomial::variable(1);
+    let three = Polynomial::constant(Cell::from(3));
+
+    // (x + 3) * (y - 1) = x*y - x + 3*y - 3
+    //
+    let sum = x.checked_add(&three).unwrap();
+    let difference = y.checked_add(&Polynomial::constant(Cell::from(-1))).unwrap();
+    let product = sum.checked_mul(&difference).unwrap();
+
+    assert_eq!(product.display(&[1, 2]), "[1]*[2] - [1] + 3*[2] - 3");
+    assert_eq!(product.evaluate(&crate::cell::from_slice(&[2, 5])), Some(Cell::from(20)));
+    assert_eq!(product.degree_in(0), 1);
+    assert_eq!(product.as_constant(), None);
+
+    let square = x.checked_mul(&x).unwrap().checked_add(&x).unwrap();
+    assert_eq!(square.display(&[7]), "[7]^2 + [7]");
+    assert_eq!(square.degree_in(0), 2);
+
+    // Terms that cancel out leave a constant.
+    //
+    let cancelled = product.checked_add(&product.checked_mul(&Polynomial::constant(Cell::from(-1))).unwrap()).unwrap();
+    assert_eq!(cancelled.as_constant(), Some(Cell::from(0)));
+    assert_eq!(cancelled.display(&[1, 2]), "0");
+}
+
+#[test]
+fn test_symbolic_nonlinear_and_any_address() {
+    use crate::cell::from_slice;
+
+    // [0] = [9] * [9] + [10], with the patched cells past the instructions.
+    //
+    let image = from_slice(&[2,9,9,0,1,0,10,0,99,0,0]);
+    let memory = execute(&Memory::from_vec(image), 0, &Cell::from(0), &[9, 10]).unwrap();
+    let output = memory.value(0).unwrap();
+
+    assert_eq!(output.display(&[9, 10]), "[9]^2 + [10]");
+    assert_eq!(solve(&output, &[-5..=5, 0..=10], &Cell::from(10)),
+        vec![
+            from_slice(&[-3, 1]), from_slice(&[-2, 6]), from_slice(&[-1, 9]), from_slice(&[0, 10]),
+            from_slice(&[1, 9]), from_slice(&[2, 6]), from_slice(&[3, 1]),
+        ]);
+}
+
+#[test]
+fn test_symbolic_errors() {
+    let run = |instructions: &[i64], patched: &[usize]| {
+        let memory = Memory::from_vec(crate::cell::from_slice(instructions));
+        execute(&memory, 0, &Cell::from(0), patched).and_then(|memory| memory.value(0))
+    };
+
+    // Jumping on a patched cell.
+    //
+    assert_eq!(run(&[1005,7,6,99,0,0,99,0], &[7]), Err(SymbolicError::Unresolvable { instruction_pointer: 0 }));
+
+    // Reading through a patched address is fine until the value is needed.
+    //
+    assert_eq!(run(&[1,5,5,0,99,0], &[1]), Err(SymbolicError::UnknownValue(0)));
+    assert_eq!(run(&[1,5,5,6,99,0,0], &[1]), Ok(Polynomial::constant(Cell::from(1))));
+
+    assert_eq!(run(&[3,0,99], &[]), Err(SymbolicError::Unsupported { instruction_pointer: 0, mnemonic: "IN" }));
+    assert!(matches!(run(&[42], &[]), Err(SymbolicError::Exec(ExecError { kind: ErrorKind::UnknownOpcode, .. }))));
+}
@@ -0,0 +1,207 @@
+
+/*!
+Machine readable execution traces.
+
+A tracer attached to a `ProgramState` sees one `TraceEvent` for every
+instruction executed. Events are formatted as JSON Lines, one object per
+instruction, so two runs can be diffed line by line or loaded by scripts:
+
+```text
+{"step":1,"ip":0,"instruction":1,"opcode":1,"mnemonic":"ADD","operands":[9,10,3],"reads":[30,40],"write":{"address":3,"value":70}}
+{"step":3,"ip":8,"instruction":99,"opcode":99,"mnemonic":"HLT","operands":[],"reads":[],"write":null}
+```
+
+`instruction` is the whole instruction word, parameter modes included, and
+`operands` holds the raw parameter cells, as they appear in memory before the
+instruction runs. `reads` holds the values the instruction read through its
+parameters, in order, after resolving their modes. `write` is the memory
+write the instruction made, if any.
+*/
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::cell::Cell;
+
+/// A single executed instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+
+    // The 1 based number of the step.
+    //
+    pub step: u64,
+    pub instruction_pointer: usize,
+
+    // The instruction word with its modes, then its opcode alone.
+    //
+    pub instruction: Cell,
+    pub opcode: u32,
+    pub mnemonic: &'static str,
+
+    // The raw parameter cells, and the values read through them.
+    //
+    pub operands: Vec<Cell>,
+    pub reads: Vec<Cell>,
+
+    // The address written and the value stored there.
+    //
+    pub write: Option<(usize, Cell)>,
+}
+
+impl TraceEvent {
+
+    /// Format the event as a single line JSON object.
+    pub fn to_json(&self) -> String {
+        let list = |cells: &[Cell]| cells.iter().map(|cell| cell.to_string()).collect::<Vec<String>>().join(",");
+
+        let write = match &self.write {
+            Some((address, value)) => format!("{{\"address\":{},\"value\":{}}}", address, value),
+            None => "null".to_string(),
+        };
+
+        format!("{{\"step\":{},\"ip\":{},\"instruction\":{},\"opcode\":{},\"mnemonic\":\"{}\",\"operands\":[{}],\"reads\":[{}],\"write\":{}}}",
+            self.step, self.instruction_pointer, self.instruction, self.opcode, self.mnemonic,
+            list(&self.operands), list(&self.reads), write)
+    }
+}
+
+/// Receives an event for every instruction a program executes.
+pub trait Tracer {
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+/// Lend a tracer to a program, keeping it available once the run is over.
+impl<T: Tracer + ?Sized> Tracer for &mut T {
+    fn trace(&mut self, event: &TraceEvent) {
+        (**self).trace(event)
+    }
+}
+
+/// Keeps every event in memory.
+#[derive(Debug, Default)]
+pub struct MemoryTracer {
+    events: Vec<TraceEvent>,
+}
+
+impl MemoryTracer {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Format every event as JSON Lines.
+    pub fn to_json_lines(&self) -> String {
+        self.events.iter().map(|event| format!("{}\n", event.to_json())).collect()
+    }
+}
+
+impl Tracer for MemoryTracer {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.events.push(event.clone());
+    }
+}
+
+/// Writes each event as a line of JSON.
+///
+/// Execution can't be interrupted by a failed write, so the first error is
+/// kept and later events are dropped. `finish` reports it.
+pub struct JsonLinesTracer<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl JsonLinesTracer<BufWriter<File>> {
+
+    /// Create, or truncate, the trace file at `path`.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+
+    pub fn new(writer: W) -> Self {
+        Self { writer, error: None }
+    }
+
+    /// Flush the trace, returning the writer or the first error seen.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.error.is_none() {
+            self.error = writeln!(self.writer, "{}", event.to_json()).err();
+        }
+    }
+}
+
+#[cfg(test)]
+fn trace_program(instructions: &[i64]) -> MemoryTracer {
+    let mut tracer = MemoryTracer::new();
+
+    {
+        let mut program_state = crate::ProgramState::from_vec(crate::cell::from_slice(instructions));
+        program_state.set_tracer(&mut tracer);
+        program_state.execute().unwrap();
+    }
+
+    tracer
+}
+
+#[test]
+fn test_trace_sample() {
+    let tracer = trace_program(&[1,9,10,3,2,3,11,0,99,30,40,50]);
+
+    assert_eq!(tracer.to_json_lines(), "\
+{\"step\":1,\"ip\":0,\"instruction\":1,\"opcode\":1,\"mnemonic\":\"ADD\",\"operands\":[9,10,3],\"reads\":[30,40],\"write\":{\"address\":3,\"value\":70}}
+{\"step\":2,\"ip\":4,\"instruction\":2,\"opcode\":2,\"mnemonic\":\"MUL\",\"operands\":[3,11,0],\"reads\":[70,50],\"write\":{\"address\":0,\"value\":3500}}
+{\"step\":3,\"ip\":8,\"instruction\":99,\"opcode\":99,\"mnemonic\":\"HLT\",\"operands\":[],\"reads\":[],\"write\":null}
+");
+}
+
+#[test]
+fn test_trace_jumps_and_output() {
+    // Output the relative base adjusted operand, then jump straight to the halt.
+    //
+    let tracer = trace_program(&[109,7,204,-1,1105,1,8,77,99]);
+    let events = tracer.events();
+
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[1].mnemonic, "OUT");
+    assert_eq!(events[1].instruction, Cell::from(204));
+    assert_eq!(events[1].operands, crate::cell::from_slice(&[-1]));
+    assert_eq!(events[1].reads, crate::cell::from_slice(&[8]));
+    assert_eq!(events[1].write, None);
+    assert_eq!(events[2].instruction_pointer, 4);
+    assert_eq!(events[2].reads, crate::cell::from_slice(&[1, 8]));
+    assert_eq!(events[3].instruction_pointer, 8);
+    assert_eq!(events[3].step, 4);
+}
+
+#[test]
+fn test_trace_json_lines_writer() {
+    let mut tracer = JsonLinesTracer::new(Vec::new());
+
+    {
+        let mut program_state = crate::ProgramState::from_vec(crate::cell::from_slice(&[1101,2,3,5,99,0]));
+        program_state.set_tracer(&mut tracer);
+        program_state.execute().unwrap();
+    }
+
+    let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+    assert_eq!(text.lines().count(), 2);
+    assert!(text.starts_with("{\"step\":1,\"ip\":0,\"instruction\":1101,\"opcode\":1,\"mnemonic\":\"ADD\",\"operands\":[2,3,5],\"reads\":[2,3],\"write\":{\"address\":5,\"value\":5}}\n"));
+}
@@ -0,0 +1,126 @@
+
+/*!
+An undo log of everything each executed instruction changed.
+
+With the log enabled on a `ProgramState`, every step records the registers
+it started from, the previous value of every cell it wrote, any input it
+consumed and whether it queued output. That's enough to put each step back
+in reverse order, so a paused machine can step backwards, run back to the
+instruction that last wrote a cell, or rewind to where the log began.
+
+The log grows by one entry per step, so it suits debugging a run rather than
+leaving it on for a program that runs for billions of steps.
+*/
+
+use crate::cell::Cell;
+
+/// What a single step changed, enough to undo it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UndoEntry {
+
+    // The registers before the step.
+    //
+    pub instruction_pointer: usize,
+    pub relative_base: Cell,
+
+    // Every address written, in order, with the value it held before. `None`
+    // is an address past the image that had never been written.
+    //
+    pub writes: Vec<(usize, Option<Cell>)>,
+
+    // The input consumed, to be given back, and whether the output produced
+    // went to the output queue, where it can be taken back if it's still there.
+    //
+    pub input: Option<Cell>,
+    pub queued_output: bool,
+}
+
+impl UndoEntry {
+
+    pub fn new(instruction_pointer: usize, relative_base: Cell) -> Self {
+        Self {
+            instruction_pointer,
+            relative_base,
+            writes: Vec::new(),
+            input: None,
+            queued_output: false,
+        }
+    }
+
+    /// Whether the step wrote to `address`.
+    pub fn wrote(&self, address: usize) -> bool {
+        self.writes.iter().any(|(written, _)| *written == address)
+    }
+}
+
+/// The steps recorded since the log was enabled or last cleared.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UndoLog {
+    entries: Vec<UndoEntry>,
+}
+
+impl UndoLog {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of steps that can be undone.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every step recorded, oldest first.
+    pub fn entries(&self) -> &[UndoEntry] {
+        &self.entries
+    }
+
+    /// How many steps have to be undone to get back to just before the last
+    /// one that wrote `address`, if any recorded step did.
+    pub fn steps_since_write(&self, address: usize) -> Option<usize> {
+        self.entries.iter().rev()
+            .position(|entry| entry.wrote(address))
+            .map(|position| position + 1)
+    }
+
+    pub fn push(&mut self, entry: UndoEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn pop(&mut self) -> Option<UndoEntry> {
+        self.entries.pop()
+    }
+
+    /// The entry for the step being executed.
+    pub fn current(&mut self) -> Option<&mut UndoEntry> {
+        self.entries.last_mut()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[test]
+fn test_undo_log_steps_since_write() {
+    let mut log = UndoLog::new();
+    assert!(log.is_empty());
+
+    for address in &[5, 6, 5, 7] {
+        let mut entry = UndoEntry::new(0, Cell::from(0));
+        entry.writes.push((*address, None));
+        log.push(entry);
+    }
+
+    log.push(UndoEntry::new(0, Cell::from(0)));
+
+    assert_eq!(log.len(), 5);
+    assert_eq!(log.steps_since_write(7), Some(2));
+    assert_eq!(log.steps_since_write(5), Some(3));
+    assert_eq!(log.steps_since_write(6), Some(4));
+    assert_eq!(log.steps_since_write(8), None);
+}